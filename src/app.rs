@@ -3,20 +3,30 @@
 use std::cmp::{max, min};
 use std::collections::{BTreeMap, BTreeSet, HashMap, LinkedList};
 use std::option::Option::Some;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use crate::message::*;
 use iced::*;
 use iced::futures::SinkExt;
 use iced::window::Mode;
 use rand::{Rng, thread_rng};
-use crate::conf::{MAX_ELEVATOR_NUM, MAX_FLOOR, MAX_PERSON_CAPACITY, MIN_FLOOR, TFloor};
+use crate::building::building;
+use crate::config::{config, Config};
+use crate::metrics::{Dashboard, Metrics, WearRecord};
+use crate::export;
+use crate::conf::{CONFIG_POLL_IN_SECONDS, DASHBOARD_REFRESH_IN_SECONDS, TFloor};
 use crate::util::*;
 use crate::floor_btn::{Direction, FloorBtnState, WaitFloorTxtState};
 use crate::icon::*;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use crate::lift::{Lift, LiftUpDownCost};
-use crate::up_down_elevator_floor::*;
+use crate::lift_actor::LiftActors;
+use crate::traffic::sample_walking;
+use crate::profile::TrafficProfile;
+use crate::cli::Options;
+use crate::snapshot::Snapshot;
+use crate::trace::{StateDigest, TraceEvent, TraceInput, TraceRecorder};
+use crate::simulation::{Simulation, Timer};
 use crate::state::State;
 use crate::state::State::{GoingDown, GoingUp, GoingUpSuspend};
 
@@ -29,33 +39,87 @@ struct ElevatorApp {
     plus_btn_state: button::State,
     subtract_btn_state: button::State,
     down_btn_state: button::State,
-    // 哪些楼层需要安排电梯去接人的
-    wait_floors: LinkedList<WaitFloorTxtState>,
-    lifts: Vec<Lift>,
+    add_lift_btn_state: button::State,
+    remove_lift_btn_state: button::State,
+    // 电梯群的模拟
+    sim: Simulation,
+    // 每部电梯对应的运行任务
+    actors: LiftActors,
+    // 程序启动（或者载入快照）的时刻，以及那时的模拟时钟, 单位：秒
+    started: Instant,
+    clock_offset: f64,
+    // 保存、载入快照使用的文件
+    snapshot_path: String,
+    save_snapshot_btn_state: button::State,
+    load_snapshot_btn_state: button::State,
+    reset_metrics_btn_state: button::State,
+    // 导出乘客行程、呼叫记录、电梯磨损统计的文件
+    trips_path: String,
+    calls_path: String,
+    wear_path: String,
+    export_btn_state: button::State,
+    // 启动时载入的配置文件，以及最后一次载入时文件的修改时间
+    config_path: Option<String>,
+    config_modified: Option<SystemTime>,
+    // 正在录制的输入轨迹
+    recorder: Option<TraceRecorder>,
+    // 每次开启自动客流，序号加一，旧生成器的到达消息会被忽略
+    traffic_seq: u32,
+    // 所选的客流，以及客流强度
+    profile: TrafficProfile,
+    intensity: f64,
+    // 客流的随机种子，不指定时每次开启客流都随机选一个
+    seed: Option<u64>,
+    traffic_btn_state: button::State,
+    profile_pick_state: pick_list::State<TrafficProfile>,
+    intensity_slider_state: slider::State,
 }
 
 impl Default for ElevatorApp {
     fn default() -> Self {
-        let mut lifts = Vec::with_capacity(MAX_ELEVATOR_NUM);
-        for no in 0..MAX_ELEVATOR_NUM {
-            lifts.push(Lift::new(no));
-        }
-        Self {
-            floor: 1,
-            tmp_floor: 0,
+        let mut app = Self {
+            floor: config().building.lobby,
+            tmp_floor: config().building.lobby,
             slider_state: Default::default(),
             up_btn_state: Default::default(),
             plus_btn_state: Default::default(),
             subtract_btn_state: Default::default(),
             down_btn_state: Default::default(),
-            wait_floors: Default::default(),
-            lifts,
+            add_lift_btn_state: Default::default(),
+            remove_lift_btn_state: Default::default(),
+            sim: Simulation::new(0.0),
+            actors: Default::default(),
+            started: Instant::now(),
+            clock_offset: 0.0,
+            snapshot_path: Default::default(),
+            save_snapshot_btn_state: Default::default(),
+            load_snapshot_btn_state: Default::default(),
+            reset_metrics_btn_state: Default::default(),
+            trips_path: Default::default(),
+            calls_path: Default::default(),
+            wear_path: Default::default(),
+            export_btn_state: Default::default(),
+            config_path: None,
+            config_modified: None,
+            recorder: None,
+            traffic_seq: 0,
+            profile: Default::default(),
+            intensity: config().traffic.intensity,
+            seed: None,
+            traffic_btn_state: Default::default(),
+            profile_pick_state: Default::default(),
+            intensity_slider_state: Default::default(),
+        };
+        app.sim.set_epoch(app.started, app.clock_offset);
+        for _ in 0..config().lifts {
+            app.add_lift();
         }
+        app
     }
 }
 
-pub fn run_window() {
-    let mut settings = Settings::default();
+pub fn run_window(opts: Options) {
+    let mut settings = Settings::with_flags(opts);
     settings.window.resizable = true; // 不能重新缩放窗口
     settings.default_font = Some(include_bytes!(
         "../assets/font/ZiTiGuanJiaFangSongTi-2.ttf"
@@ -70,146 +134,60 @@ const MAX_WAIT_FLOOR_NUM: usize = (BTN_PER_ROW * MAX_WAIT_FLOOR_ROW_NUM) as usiz
 
 
 impl ElevatorApp {
-    const fn floor_rows() -> i32 {
-        Self::calc_rows2(MAX_FLOOR - MIN_FLOOR, BTN_PER_ROW)
+    fn floor_rows() -> i32 {
+        Self::calc_rows2(building().len() as i32, BTN_PER_ROW)
     }
 
-    fn remove_wait_floor(wait_floors: &mut LinkedList<WaitFloorTxtState>, floor: TFloor, lift: &mut Lift)
-    {
-        let direction = lift.remove_floor(floor);
-        if let Some(direct) = direction {
-            // 删除正在等待的楼层
-            loop {
-                if let Some((idx, _)) = wait_floors
-                    .iter()
-                    .enumerate()
-                    .find(|(_, wf)| wf.floor == floor && wf.direction == direct
-                    ) {
-                    println!("remove_wait_floor {}, 索引:{}", lift.to_string(), idx);
-                    let mut after = wait_floors.split_off(idx);
-                    after.pop_front(); // 删除首部元素， 再跟原来的 list 拼接起来
-                    wait_floors.append(&mut after);
-                } else {
-                    break;
-                }
-            }
+    // 仪表盘：候梯时间、等待的呼叫、运送人数和每部电梯的利用率
+    fn dashboard_view(d: Dashboard, reset_btn_state: &mut button::State) -> Element<AppMessage> {
+        fn row<'a>(name: &str, value: String) -> Element<'a, AppMessage> {
+            Row::with_children(vec![
+                Text::new(name).width(Length::FillPortion(3)).into(),
+                Text::new(value).width(Length::FillPortion(2)).into(),
+            ]).spacing(10).padding(4).into()
         }
+        // 还没有人乘完电梯时没有候梯时间
+        let secs = |v: f64| if v.is_nan() { "-".to_string() } else { format!("{:.1}秒", v) };
+        let mut children = vec![
+            Text::new("实时指标").size(24).into(),
+            row("运送人数:", d.carried.to_string()),
+            row("平均候梯时间:", secs(d.waiting.mean)),
+            row("候梯时间P95:", secs(d.waiting.p95)),
+            row("等待的呼叫:", d.calls.to_string()),
+            row("候梯人数:", d.waiting_passengers.to_string()),
+            row("当前最长等待:", secs(d.longest_wait)),
+        ];
+        for (no, ratio) in d.utilisation.iter().enumerate() {
+            children.push(row(&format!("电梯#{}利用率:", no + 1), format!("{:.0}%", ratio * 100.0)));
+            children.push(ProgressBar::new(0.0..=1.0, *ratio as f32)
+                .height(Length::Units(6))
+                .into());
+        }
+        children.push(Button::new(reset_btn_state, Text::new("重置统计"))
+            .on_press(AppMessage::ClickedBtnResetMetrics)
+            .into());
+        Column::with_children(children)
+            .spacing(4)
+            .padding(4)
+            .width(Length::FillPortion(1))
+            .into()
     }
 
-    fn new_up_down_elevator(&self, floor: TFloor, typ: FloorType) -> UpDownElevatorFloor {
-        match typ {
-            FloorType::PersonUp | FloorType::PersonDown => UpDownElevatorFloor {
-                floor,
-                typ,
-                state: EState::Noop,
-            },
-            FloorType::Elevator(no) => {
-                UpDownElevatorFloor {
-                    floor,
-                    typ,
-                    state: match self.lifts[no].state {
-                        State::Stop => EState::Stop,
-                        State::Maintaining => EState::Noop,
-                        _ => EState::Running
-                    },
-                }
-            }
-        }
+    // 新增一部电梯，并启动它的运行任务
+    fn add_lift(&mut self) -> usize {
+        let no = self.sim.add_lift();
+        self.actors.spawn(no);
+        no
     }
 
-    fn schedule2(&mut self, floor: TFloor, direction: Direction) -> Command<AppMessage> {
-        let mut a = vec![
-            self.new_up_down_elevator(floor, match direction {
-                Direction::Up => FloorType::PersonUp,
-                Direction::Down => FloorType::PersonDown,
-            })];
-        a.extend(self.lifts
-            .iter()
-            .filter(|lift| lift.state == State::Stop ||
-                match direction {
-                    Direction::Up => ((lift.state == State::GoingUp || lift.state == State::GoingUpSuspend)
-                        && lift.cur_floor <= floor),
-                    Direction::Down => ((lift.state == State::GoingDown || lift.state == State::GoingDownSuspend)
-                        && lift.cur_floor >= floor),
-                })
-            // 不能超载
-            .filter(|lift| !lift.is_overload())
-            .map(|o| self.new_up_down_elevator(o.cur_floor, FloorType::Elevator(o.no)))
-        );
-        a.shrink_to_fit();
-        match direction {
-            Direction::Up => a.sort(),
-            Direction::Down => a.sort_by(|a, b| b.cmp(a)),
-        }
-        let mut top_lift = None;
-        let mut down_lift = None;
-        let mut find = false;
-        for item in a {
-            match item.typ {
-                FloorType::PersonUp | FloorType::PersonDown => find = true,
-                FloorType::Elevator(no) => {
-                    if !find {
-                        top_lift = Some(no);
-                    } else {
-                        down_lift = Some(no);
-                        break;
-                    }
-                }
+    // 把模拟要的定时器交给对应电梯的运行任务，到期后从电梯任务的状态广播收到消息
+    fn flush(&mut self) {
+        for timer in self.sim.take_timers() {
+            match timer {
+                Timer::RunOneFloor(no, millis) => self.actors.run_one_floor(no, millis),
+                Timer::Door(no, millis, seq) => self.actors.door_timer(no, millis, seq),
             }
         }
-        if top_lift.is_some() || down_lift.is_some() {
-            let lift_idx;
-            match top_lift {
-                Some(top) => {
-                    match down_lift {
-                        Some(down) => {
-                            let top_diff = (floor - self.lifts[top].cur_floor).abs();
-                            let down_diff = (self.lifts[down].cur_floor - floor).abs();
-                            if top_diff >= down_diff {
-                                lift_idx = down
-                            } else {
-                                lift_idx = top;
-                            }
-                        }
-                        _ => lift_idx = top
-                    }
-                }
-                _ => {
-                    match down_lift {
-                        Some(down) => {
-                            lift_idx = down;
-                        }
-                        _ => unreachable!()
-                    }
-                }
-            }
-            let lift = &mut self.lifts[lift_idx];
-            lift.schedule_floors.insert(floor, Some(direction));
-            let mut is_wait = false;
-            if lift.state == State::Stop {
-                is_wait = true;
-                if lift.cur_floor > floor {
-                    lift.state = State::GoingDown;
-                } else if lift.cur_floor < floor {
-                    lift.state = State::GoingUp;
-                } else {
-                    // 在同一个楼层时， 就开门进出人就可以了
-                    match direction {
-                        Direction::Up => lift.state = State::GoingUpSuspend,
-                        Direction::Down => lift.state = State::GoingDownSuspend,
-                    }
-                    lift.set_persons();
-                }
-            }
-            self.wait_floors
-                .iter_mut()
-                .filter(|wf| wf.floor == floor && wf.direction == direction)
-                .for_each(|wf| wf.is_scheduled = true);
-            return Command::perform(async move {
-                Lift::suspend_one_by_one_floor(lift_idx, is_wait).await
-            }, |msg| msg);
-        }
-        Command::none()
     }
 
     const fn calc_rows2(total: i32, per: i32) -> i32 {
@@ -234,31 +212,260 @@ impl ElevatorApp {
         }
     }
 
+    // 在所选楼层按了上行或下行按钮，来了一位乘客
     fn add_to_wait_floor(&mut self, direction: Direction) -> Command<AppMessage> {
-        let fi = WaitFloorTxtState {
+        let floor = self.floor;
+        if MAX_WAIT_FLOOR_NUM <= self.sim.wait_floors.len() {
+            println!("电梯繁忙，请稍后再试,{}", floor);
+            return Command::none();
+        }
+        let destination = match random_destination(floor, direction) {
+            Some(destination) => destination,
+            None => {
+                println!("{}层不能往{}走", floor, direction);
+                return Command::none();
+            }
+        };
+        let walking = sample_walking(self.sim.behaviour.walking, &mut thread_rng());
+        let weight = self.sim.behaviour.weight.sample(&mut thread_rng());
+        self.set_random_floor();
+        self.apply_input(TraceInput::HallCall {
+            floor,
+            destination,
+            walking,
+            weight,
+        })
+    }
+
+    // 按所选的客流和强度开启自动客流，已经开启的客流会被替换掉
+    fn start_traffic(&mut self) -> Command<AppMessage> {
+        let seed = self.seed.unwrap_or_else(|| thread_rng().gen());
+        self.apply_input(TraceInput::StartTraffic {
+            profile: self.profile,
+            intensity: self.intensity,
+            seed,
+        })
+    }
+
+    fn stop_traffic(&mut self) {
+        self.traffic_seq = self.traffic_seq.wrapping_add(1);
+        self.sim.stop_traffic();
+    }
+
+    // 等待自动客流的下一位乘客到达
+    fn wait_traffic(&self) -> Command<AppMessage> {
+        match self.sim.next_arrival_at() {
+            Some(at) => {
+                let delay = (at - self.now()).max(0.0);
+                let seq = self.traffic_seq;
+                Command::perform(tokio::time::sleep(Duration::from_secs_f64(delay)),
+                                 move |_| AppMessage::TrafficArrival(seq))
+            }
+            None => Command::none(),
+        }
+    }
+
+    // 自动客流的乘客到达候梯厅，并呼梯
+    fn on_traffic_arrival(&mut self, seq: u32) -> Command<AppMessage> {
+        if seq != self.traffic_seq {
+            return Command::none();
+        }
+        let cmd = self.apply_input(TraceInput::TrafficArrival);
+        Command::batch(vec![cmd, self.wait_traffic()])
+    }
+
+    // 模拟时钟：程序启动后经过的时间，载入快照后从快照的时钟接着走, 单位：秒
+    fn now(&self) -> f64 {
+        self.clock_offset + self.started.elapsed().as_secs_f64()
+    }
+
+    // 整个模拟的快照，加上界面上选的楼层和客流，模拟时钟要先走到现在
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
             floor: self.floor,
-            direction,
-            is_scheduled: false,
+            tmp_floor: self.tmp_floor,
+            profile: self.profile,
+            intensity: self.intensity,
+            seed: self.seed,
+            ..Snapshot::capture(&self.sim)
+        }
+    }
+
+    fn save_snapshot(&mut self) -> std::result::Result<(), String> {
+        self.sim.advance(self.now());
+        self.snapshot().save(&self.snapshot_path)
+    }
+
+    // 导出乘完电梯的乘客的行程、应答了的呼叫，以及每部电梯的磨损统计
+    fn export_records(&self) -> std::result::Result<(usize, usize), String> {
+        let rides = Metrics::from_passengers(&self.sim.passengers.finished);
+        let trips = export::export(&self.trips_path, rides.rides())?;
+        let calls = export::export(&self.calls_path, self.sim.metrics.calls())?;
+        let wear = self.sim.lifts
+            .iter()
+            .map(|lift| WearRecord::of(lift.no, &lift.wear))
+            .collect::<Vec<_>>();
+        export::export(&self.wear_path, &wear)?;
+        Ok((trips, calls))
+    }
+
+    // 载入快照，从快照保存的那一刻接着运行
+    // 原来的电梯任务、开关门定时器和客流都会作废
+    fn load_snapshot(&mut self) -> std::result::Result<Command<AppMessage>, String> {
+        let snapshot = Snapshot::load(&self.snapshot_path)?;
+        Ok(self.apply_snapshot(snapshot))
+    }
+
+    fn apply_snapshot(&mut self, snapshot: Snapshot) -> Command<AppMessage> {
+        let now = Instant::now();
+        self.started = now;
+        self.clock_offset = snapshot.clock;
+        self.floor = snapshot.floor;
+        self.tmp_floor = snapshot.tmp_floor;
+        self.profile = snapshot.profile;
+        self.intensity = snapshot.intensity;
+        self.seed = snapshot.seed;
+        self.stop_traffic();
+        self.actors = LiftActors::default();
+        snapshot.restore(&mut self.sim, now);
+        for no in 0..self.sim.lifts.len() {
+            self.actors.spawn(no);
+        }
+        self.flush();
+        self.wait_traffic()
+    }
+
+    // 处理一次输入（界面上的操作、定时器到期、定时检查），录制时连同处理时的模拟时钟和处理后的状态摘要一起写入轨迹
+    // 模拟的部分交给 Simulation::apply_input，轨迹回放时也用它，保证回放和录制时的处理完全一样
+    fn apply_input(&mut self, input: TraceInput) -> Command<AppMessage> {
+        let at = self.now();
+        self.sim.advance(at);
+        let lifts = self.sim.lifts.len();
+        self.sim.apply_input(&input);
+        // 电梯的运行任务和自动客流的定时器在界面这边
+        let cmd = match &input {
+            TraceInput::AddLift { .. } | TraceInput::RemoveLift => {
+                if self.sim.lifts.len() > lifts {
+                    self.actors.spawn(lifts);
+                } else if self.sim.lifts.len() < lifts {
+                    self.actors.remove(lifts - 1);
+                }
+                Command::none()
+            }
+            TraceInput::StartTraffic { profile, intensity, .. } => {
+                self.traffic_seq = self.traffic_seq.wrapping_add(1);
+                self.profile = *profile;
+                self.intensity = *intensity;
+                self.wait_traffic()
+            }
+            TraceInput::StopTraffic => {
+                self.traffic_seq = self.traffic_seq.wrapping_add(1);
+                Command::none()
+            }
+            _ => Command::none(),
         };
-        if MAX_WAIT_FLOOR_NUM > self.wait_floors.len() {
-            if !self.wait_floors.contains(&fi) {
-                self.wait_floors.push_back(fi);
+        self.flush();
+        if self.recorder.is_some() {
+            let event = TraceEvent {
+                at,
+                input,
+                digest: self.digest(),
+            };
+            if let Err(e) = self.recorder.as_mut().unwrap().record(&event) {
+                println!("{}，停止录制", e);
+                self.recorder = None;
             }
-        } else {
-            println!("电梯繁忙，请稍后再试,{}", self.floor);
         }
-        self.set_random_floor();
-        self.schedule2(fi.floor, direction)
+        cmd
+    }
+
+    // 配置文件修改过的话重新载入，有错误或者改了不能在运行时修改的项时，继续用原来的配置
+    fn reload_config(&mut self) -> Command<AppMessage> {
+        let path = match &self.config_path {
+            Some(path) => path.clone(),
+            None => return Command::none(),
+        };
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.config_modified {
+            return Command::none();
+        }
+        self.config_modified = modified;
+        let new = match Config::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                println!("{}\n继续使用原来的配置", e);
+                return Command::none();
+            }
+        };
+        if let Err(e) = config().check_reload(&new) {
+            println!("配置文件{}没有重新载入:\n  {}", path, e.join("\n  "));
+            return Command::none();
+        }
+        println!("重新载入配置文件{}", path);
+        self.apply_input(TraceInput::Reconfigure(Box::new(new)))
+    }
+
+    fn digest(&self) -> StateDigest {
+        StateDigest::capture(&self.sim.lifts, &self.sim.wait_floors, &self.sim.passengers)
+    }
+
+    // 从现在的状态开始录制输入轨迹
+    fn start_recording(&mut self, path: &str) -> std::result::Result<(), String> {
+        self.sim.advance(self.now());
+        self.recorder = Some(TraceRecorder::create(path, self.snapshot())?);
+        println!("开始录制输入轨迹{}", path);
+        Ok(())
     }
 }
 
 impl Application for ElevatorApp {
     type Executor = executor::Default;
     type Message = AppMessage;
-    type Flags = ();
+    type Flags = Options;
 
-    fn new(_: Self::Flags) -> (Self, Command<Self::Message>) {
-        (Self::default(), Command::none())
+    fn new(opts: Self::Flags) -> (Self, Command<Self::Message>) {
+        let mut app = Self::default();
+        if let Some(intensity) = opts.intensity {
+            app.intensity = intensity;
+        }
+        app.seed = opts.seed;
+        app.sim.dispatch = opts.dispatch;
+        app.snapshot_path = opts.snapshot;
+        app.trips_path = opts.trips;
+        app.calls_path = opts.calls;
+        app.wear_path = opts.wear;
+        app.config_modified = opts.config
+            .as_ref()
+            .and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+        app.config_path = opts.config;
+        let started = if opts.load {
+            // 快照里已经包含了客流，命令行指定的客流不再生效
+            app.load_snapshot().map(|cmd| {
+                println!("已载入快照{}", app.snapshot_path);
+                cmd
+            })
+        } else if let Some(profile) = opts.profile {
+            // 命令行指定了客流时，启动后马上开启
+            app.profile = profile;
+            Ok(app.start_traffic())
+        } else {
+            Ok(Command::none())
+        };
+        let cmd = match started {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        // 从启动完成时的状态开始录制
+        if let Some(path) = &opts.record {
+            if let Err(e) = app.start_recording(path) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        (app, cmd)
     }
 
     fn title(&self) -> String {
@@ -268,32 +475,20 @@ impl Application for ElevatorApp {
     fn update(&mut self, message: Self::Message, clipboard: &mut Clipboard) -> Command<Self::Message> {
         // println!("{:?}", message);
         match message {
-            AppMessage::SliderChange(floor) => {
-                if floor != 0 {
-                    self.tmp_floor = floor;
-                }
+            AppMessage::SliderChange(idx) => {
+                self.tmp_floor = building().floor_at(idx as usize);
             }
             AppMessage::SliderRelease(floor) => {
-                if floor != 0 {
-                    self.floor = floor;
-                }
+                self.floor = floor;
             }
             AppMessage::ClickedBtnPlus => {
-                if self.floor == -1 {
-                    self.floor = 1
-                } else {
-                    self.floor += 1
-                }
-                self.floor = min(self.floor, MAX_FLOOR);
+                // 已经是最高层时不变
+                self.floor = next_floor(self.floor, Direction::Up).unwrap_or(self.floor);
                 self.tmp_floor = self.floor;
             }
             AppMessage::ClickedBtnSubtract => {
-                if self.floor == 1 {
-                    self.floor = -1
-                } else {
-                    self.floor -= 1
-                }
-                self.floor = max(self.floor, MIN_FLOOR);
+                // 已经是最低层时不变
+                self.floor = next_floor(self.floor, Direction::Down).unwrap_or(self.floor);
                 self.tmp_floor = self.floor;
             }
             AppMessage::ClickedBtnUp => {
@@ -302,184 +497,107 @@ impl Application for ElevatorApp {
             AppMessage::ClickedBtnDown => {
                 return self.add_to_wait_floor(Direction::Down);
             }
-            AppMessage::Scheduling => {
-                return Command::batch(self
-                    .wait_floors
-                    .iter()
-                    .filter(|wf| !wf.is_scheduled)
-                    .map(|wf| {
-                        let floor = wf.floor;
-                        let direction = wf.direction;
-                        Command::perform(async {}, move |_| {
-                            AppMessage::Scheduling2(floor, direction)
-                        })
-                    })
-                );
-            }
-            AppMessage::LiftRunning => {
-                return Command::batch(self
-                    .lifts
+            AppMessage::Tick => {
+                let now = Instant::now();
+                self.sim.lifts
                     .iter_mut()
-                    .filter(
-                        |lift| lift.state != State::Maintaining &&
-                            !lift.stop_floors.is_empty()
-                    )
-                    .map(|lift| {
-                        let no = lift.no;
-                        if lift.state == State::Stop {
-                            if let Some(first) = lift.stop_floors.keys().into_iter().collect::<BTreeSet<_>>()
-                                .union(&lift.schedule_floors.keys().into_iter().collect::<BTreeSet<_>>()).next() {
-                                if **first > lift.cur_floor {
-                                    lift.state = State::GoingUp
-                                } else if **first < lift.cur_floor {
-                                    lift.state = State::GoingDown
-                                } else {
-                                    lift.state = State::GoingUpSuspend
-                                }
-                            }
-                        }
-                        println!("LiftRunning {},{}", lift.to_string(),
-                                 lift.stop_floors.keys().map(|k| k.to_string())
-                                     .collect::<Vec<_>>().join(","));
-                        Command::perform(async move {
-                            Lift::suspend_one_by_one_floor(no, false).await
-                        }, move |msg| msg,
-                        )
-                    })
-                );
+                    .for_each(|lift| lift.update_position(now));
             }
-            AppMessage::Scheduling2(floor, direction) => {
-                return self.schedule2(floor, direction);
+            AppMessage::ClickedBtnTraffic => {
+                if self.sim.traffic.is_some() {
+                    return self.apply_input(TraceInput::StopTraffic);
+                } else {
+                    return self.start_traffic();
+                }
             }
-
-            AppMessage::ArriveByOneFloor(no) => {
-                let lift = &mut self.lifts[no];
-                let no = lift.no;
-                return if let Some(dest_floor) = lift.dest_floor() {
-                    lift.set_lift_btn_click();
-                    println!("ArriveByOneFloor_Some, {}, {}", lift.to_string(), dest_floor);
-                    if lift.state == State::Stop{
-                        if lift.cur_floor < dest_floor{
-                            lift.state = State::GoingUp;
-                        }else if lift.cur_floor > dest_floor{
-                            lift.state = State::GoingDown;
-                        }
-                    }
-                    if lift.state == State::GoingUp {
-                        // 避免出现楼层为 0 的情况
-                        if lift.cur_floor == -1 {
-                            lift.cur_floor = 1;
-                        } else {
-                            lift.cur_floor += 1;
-                        }
-                    } else if lift.state == State::GoingDown {
-                        if lift.cur_floor == 1 {
-                            lift.cur_floor = -1;
-                        } else {
-                            lift.cur_floor -= 1;
-                        }
-                    }
-                    let is_arrive = lift.cur_floor == dest_floor;
-                    if is_arrive {
-                        lift.state = match lift.state {
-                            State::GoingUp => State::GoingUpSuspend,
-                            State::GoingUpSuspend => State::GoingUp,
-                            State::GoingDown => State::GoingDownSuspend,
-                            State::GoingDownSuspend => State::GoingDown,
-                            State::Stop => State::Stop,
-                            _ => {
-                                println!("ArriveByOneFloor {}", lift.to_string());
-                                unreachable!()
-                            }
-                        };
-                        Self::remove_wait_floor(&mut self.wait_floors, dest_floor, lift);
-                        println!("ArriveByOneFloor {},已达到楼层{},正在等人进出。", lift.to_string(), dest_floor);
-                        return Command::perform(async move {}, move |_| AppMessage::WaitUserInputFloor(no));
+            AppMessage::SelectTrafficProfile(profile) => {
+                self.profile = profile;
+                if self.sim.traffic.is_some() {
+                    return self.start_traffic();
+                }
+            }
+            AppMessage::TrafficIntensityChange(intensity) => {
+                self.intensity = intensity;
+                if self.sim.traffic.is_some() {
+                    return self.start_traffic();
+                }
+            }
+            AppMessage::TrafficArrival(seq) => {
+                return self.on_traffic_arrival(seq);
+            }
+            AppMessage::ToggleStairs(stairs) => {
+                return self.apply_input(TraceInput::Stairs(stairs));
+            }
+            AppMessage::PassengerTick => {
+                return self.apply_input(TraceInput::PassengerTick);
+            }
+            AppMessage::ClickedBtnSaveSnapshot => {
+                match self.save_snapshot() {
+                    Ok(_) => println!("已保存快照{}", self.snapshot_path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            AppMessage::ClickedBtnExport => {
+                match self.export_records() {
+                    Ok((trips, calls)) => println!("{}条乘客行程已导出到{}，{}条呼叫记录已导出到{}，电梯磨损统计已导出到{}",
+                                                   trips, self.trips_path, calls, self.calls_path, self.wear_path),
+                    Err(e) => println!("{}", e),
+                }
+            }
+            AppMessage::ClickedBtnLoadSnapshot => {
+                // 载入快照后，录制的轨迹无法再对应上
+                if self.recorder.take().is_some() {
+                    println!("载入快照，停止录制输入轨迹");
+                }
+                match self.load_snapshot() {
+                    Ok(cmd) => {
+                        println!("已载入快照{}", self.snapshot_path);
+                        return cmd;
                     }
-                    let no = lift.no;
-                    Command::perform(async move {
-                        Lift::suspend_one_by_one_floor(no, is_arrive).await
-                    }, |msg| msg)
-                } else {
-                    println!("ArriveByOneFloor_None, {}", lift.to_string());
-                    lift.state = State::Stop;
-                    lift.set_lift_btn_click();
-                    Command::none()
-                };
-            }
-
-            AppMessage::WaitUserInputFloor(no) => {
-                let lift = &mut self.lifts[no];
-                match lift.state {
-                    State::GoingUpSuspend => lift.state = State::GoingUp,
-                    State::GoingDownSuspend => lift.state = State::GoingDown,
-                    _ => {}
-                };
-                lift.set_persons();
-                lift.can_click_btn = lift.persons > 0;
-                println!("_WaitUserInputFloor {}", lift.to_string());
-                return Command::perform(async move {
-                    Lift::suspend_one_by_one_floor(no, false).await
-                }, |msg| msg);
+                    Err(e) => println!("{}", e),
+                }
+            }
+            AppMessage::ClickedBtnAddLift => {
+                // 新电梯的初始楼层在这里随机取好，录制下来回放时才一样
+                let floor = Lift::new(self.sim.lifts.len()).cur_floor;
+                return self.apply_input(TraceInput::AddLift { floor });
+            }
+            AppMessage::ClickedBtnRemoveLift => {
+                return self.apply_input(TraceInput::RemoveLift);
+            }
+            AppMessage::CheckConfig => {
+                return self.reload_config();
+            }
+            AppMessage::ClickedBtnResetMetrics => {
+                let now = self.now();
+                self.sim.advance(now);
+                self.sim.metrics.clear();
+                self.sim.utilisation.reset(&self.sim.lifts, now);
+            }
+            AppMessage::Scheduling => {
+                return self.apply_input(TraceInput::Scheduling);
             }
 
-            AppMessage::ClickedBtnFloor(no, floor) => {
-                let lift = &mut self.lifts[no];
-                if lift.can_click_btn {
-                    let btn = lift.elevator_btns
-                        .iter_mut()
-                        .find(|o| o.floor == floor)
-                        .unwrap();
-                    btn.is_active = !btn.is_active;
-                    btn.last_pressed = Some(Instant::now());
-                    let first_floor = lift.stop_floors.iter().next().map(|o| *o.0);
-                    if btn.is_active {
-                        let mut can_insert = match first_floor {
-                            None => true,
-                            Some(_) => {
-                                match lift.state {
-                                    State::GoingUp | State::GoingUpSuspend => floor > lift.cur_floor,
-                                    State::GoingDown | State::GoingDownSuspend => floor < lift.cur_floor,
-                                    State::Stop => true,
-                                    _ => false,
-                                }
-                            }
-                        };
-                        if can_insert {
-                            lift.stop_floors.insert(floor, None);
-                        }
-                    } else {
-                        if lift.stop_floors.len() > 1 {
-                            // 超过一个输入时， 才允许删除
-                            lift.stop_floors.remove(&floor);
-                        } else {
-                            btn.is_active = true;
-                        }
-                    }
+            AppMessage::ArriveByOneFloor(no) => {
+                return self.apply_input(TraceInput::ArriveByOneFloor(no));
+            }
 
-                    if lift.state == State::Stop {
-                        // 有且只有一个输入时， 第一个楼层决定电梯的运行方向
-                        if lift.stop_floors.len() == 1 {
-                            if let Some(floor) = lift.stop_floors.iter().next().map(|o| *o.0) {
-                                if lift.cur_floor < floor {
-                                    lift.state = State::GoingUp
-                                } else {
-                                    lift.state = State::GoingDown
-                                }
-                                println!("elevator_btns,{}", lift.to_string());
-                                lift.set_lift_btn_click();
-                            }
-                        }
-                    }
-                }
+            AppMessage::DoorTimer(no, seq) => {
+                return self.apply_input(TraceInput::DoorTimer(no, seq));
+            }
+            AppMessage::ClickedBtnDoorOpen(no) => {
+                return self.apply_input(TraceInput::DoorOpen(no));
+            }
+            AppMessage::ClickedBtnDoorClose(no) => {
+                return self.apply_input(TraceInput::DoorClose(no));
+            }
 
+            AppMessage::ClickedBtnMaintain(no) => {
+                return self.apply_input(TraceInput::Maintain(no));
+            }
 
-                println!("{}, {}", lift.to_string(), lift.stop_floors
-                    .keys()
-                    .into_iter()
-                    .map(|o| o.to_string())
-                    .collect::<Vec<_>>()
-                    .join(","));
+            AppMessage::ClickedBtnFloor(no, floor) => {
+                return self.apply_input(TraceInput::CarCall { lift: no, floor });
             }
             _ => {}
         }
@@ -487,26 +605,50 @@ impl Application for ElevatorApp {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        // 每隔3秒检查一次是否有用户要乘电梯，有的话，就要去调度
-        Subscription::batch(vec![
-            time::every(Duration::from_secs(5))
+        // 调度由呼叫、电梯状态变化等事件触发，这里只是兜底，防止有呼叫被漏掉
+        let mut subs = vec![
+            time::every(Duration::from_secs(config().scheduling_fallback))
                 .map(|_| AppMessage::Scheduling),
-            time::every(Duration::from_secs(5))
-                .map(|_| AppMessage::LiftRunning),
-        ])
+            // 电梯运行完一层楼、开关门的定时器到期
+            self.actors.subscription(),
+        ];
+        if self.sim.lifts.iter().any(|lift| lift.trip.is_some()) {
+            // 有电梯在运行时，定时刷新电梯的实时位置
+            subs.push(time::every(Duration::from_millis(config().refresh_interval as u64))
+                .map(|_| AppMessage::Tick));
+        } else {
+            // 仪表盘上的等待时间、利用率随时间变化，电梯不动时也要刷新
+            subs.push(time::every(Duration::from_secs(DASHBOARD_REFRESH_IN_SECONDS))
+                .map(|_| AppMessage::Tick));
+        }
+        if self.sim.behaviour.stairs_patience.is_some() && self.sim.passengers.total_waiting() > 0 {
+            // 有人候梯时，每秒看看有没有人要改走楼梯
+            subs.push(time::every(Duration::from_secs(1))
+                .map(|_| AppMessage::PassengerTick));
+        }
+        if self.config_path.is_some() {
+            // 配置文件修改后自动重新载入
+            subs.push(time::every(Duration::from_secs(CONFIG_POLL_IN_SECONDS))
+                .map(|_| AppMessage::CheckConfig));
+        }
+        Subscription::batch(subs)
     }
 
     fn view(&mut self) -> Element<'_, Self::Message> {
         let mut subs = vec![];
+        // 滑块按楼层的索引移动，楼层号不连续也没关系
+        let building = building();
+        let dashboard = Dashboard::capture(&self.sim, self.now());
         let slider = Slider::new(
             &mut self.slider_state,
-            (MIN_FLOOR..=MAX_FLOOR),
-            self.tmp_floor,
+            0..=(building.len() - 1) as i32,
+            building.index(self.tmp_floor).unwrap_or(0) as i32,
             AppMessage::SliderChange)
             .on_release(AppMessage::SliderRelease(self.tmp_floor))
             .width(Length::FillPortion(2))
             .into();
-        let floor = Text::new(&format!("{}", self.floor))
+        // 拖动滑块时显示正在选的楼层
+        let floor = Text::new(building.name(self.tmp_floor))
             .width(Length::Units(30))
             .into();
         let e = Text::new("所在楼层: ")
@@ -544,13 +686,64 @@ impl Application for ElevatorApp {
         subs.push(floor);
         subs.push(Space::with_width(Length::Units(20)).into());
         subs.push(up_btn_row);
-        subs.push(Space::with_width(Length::FillPortion(2)).into());
+        subs.push(Button::new(&mut self.add_lift_btn_state, Text::new("加电梯"))
+                      .on_press(AppMessage::ClickedBtnAddLift)
+                      .into(), );
+        subs.push(Space::with_width(Length::Units(5)).into());
+        subs.push(Button::new(&mut self.remove_lift_btn_state, Text::new("减电梯"))
+                      .on_press(AppMessage::ClickedBtnRemoveLift)
+                      .into(), );
+        subs.push(Space::with_width(Length::FillPortion(1)).into());
+        let traffic_row = Row::with_children(vec![
+            Text::new("客流:").into(),
+            PickList::new(&mut self.profile_pick_state,
+                          &TrafficProfile::ALL[..],
+                          Some(self.profile),
+                          AppMessage::SelectTrafficProfile)
+                .width(Length::Units(100))
+                .into(),
+            Space::with_width(Length::Units(20)).into(),
+            Text::new("强度:").into(),
+            Slider::new(&mut self.intensity_slider_state,
+                        1.0..=30.0,
+                        self.intensity,
+                        AppMessage::TrafficIntensityChange)
+                .step(0.5)
+                .width(Length::Units(200))
+                .into(),
+            Text::new(format!("{:.1}%/5分钟", self.intensity))
+                .width(Length::Units(120))
+                .into(),
+            Button::new(&mut self.traffic_btn_state,
+                        Text::new(if self.sim.traffic.is_some() { "停止客流" } else { "开启客流" }))
+                .on_press(AppMessage::ClickedBtnTraffic)
+                .into(),
+            Space::with_width(Length::Units(20)).into(),
+            Checkbox::new(self.sim.behaviour.stairs_patience.is_some(),
+                          "短途等太久走楼梯",
+                          AppMessage::ToggleStairs)
+                .into(),
+            Space::with_width(Length::Units(20)).into(),
+            Button::new(&mut self.save_snapshot_btn_state, Text::new("保存快照"))
+                .on_press(AppMessage::ClickedBtnSaveSnapshot)
+                .into(),
+            Button::new(&mut self.load_snapshot_btn_state, Text::new("载入快照"))
+                .on_press(AppMessage::ClickedBtnLoadSnapshot)
+                .into(),
+            Button::new(&mut self.export_btn_state, Text::new("导出记录"))
+                .on_press(AppMessage::ClickedBtnExport)
+                .into(),
+        ]).padding(4)
+            .spacing(6)
+            .align_items(Align::Center)
+            .into();
         let mut rows = vec![
             Column::with_children(vec![
                 Row::with_children(subs)
                     .padding(4)
                     .width(Length::Fill)
                     .align_items(Align::Center).into(),
+                traffic_row,
                 Container::new(Row::with_children(
                     vec![
                         Container::new(
@@ -563,8 +756,8 @@ impl Application for ElevatorApp {
                             let mut i = 1;
                             let mut rows = vec![];
                             let mut row_elements = vec![];
-                            for f in self.
-                                wait_floors
+                            for f in self.sim
+                                .wait_floors
                                 .iter_mut()
                                 .fold(vec![], |mut row, txt| {
                                     row.push(txt.floor_view());
@@ -603,9 +796,10 @@ impl Application for ElevatorApp {
                 .spacing(2)
                 .into(),
         ];
-        let new_rows = self.lifts
+        let dashboard = Self::dashboard_view(dashboard, &mut self.reset_metrics_btn_state);
+        let lift_rows = self.sim.lifts
             .iter_mut()
-            .fold(rows, |mut _rows, lift| {
+            .fold(vec![], |mut _rows, lift| {
                 let status = Column::with_children(
                     vec![
                         Row::with_children(vec![
@@ -634,17 +828,74 @@ impl Application for ElevatorApp {
                         ]).spacing(10).padding(4).into(),
                         Row::with_children(vec![
                             Text::new("所在楼层:").width(Length::FillPortion(1)).into(),
-                            Text::new(format!("{}", lift.cur_floor)).width(Length::FillPortion(2)).into(),
+                            Text::new(building.name(lift.cur_floor)).width(Length::FillPortion(2)).into(),
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Text::new("高度:").width(Length::FillPortion(1)).into(),
+                            Text::new(format!("{:.1}米", lift.position)).width(Length::FillPortion(2)).into(),
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            ProgressBar::new(0.0..=floor_position(building.highest()), lift.position)
+                                .height(Length::Units(6))
+                                .into(),
                         ]).spacing(10).padding(4).into(),
                         Row::with_children(vec![
                             Text::new("人数:").width(Length::FillPortion(1)).into(),
-                            Text::new(format!("{}", lift.persons)).width(Length::FillPortion(2)).into(),
+                            Text::new(format!("{}", lift.persons())).width(Length::FillPortion(2)).into(),
+                        ], ).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Text::new("载重:").width(Length::FillPortion(1)).into(),
+                            Text::new(format!("{:.0}/{:.0}千克", lift.load(), lift.rated_load))
+                                .width(Length::FillPortion(2))
+                                .into(),
+                            if lift.is_overload() {
+                                Text::new("超载")
+                                    .color(Color::from_rgb8(255, 0, 0))
+                                    .width(Length::Units(40))
+                                    .into()
+                            } else {
+                                Text::new("")
+                                    .width(Length::Units(40))
+                                    .into()
+                            },
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Text::new("电梯门:").width(Length::FillPortion(1)).into(),
+                            Text::new(lift.door.state.to_string()).width(Length::FillPortion(2)).into(),
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Text::new("行程:").width(Length::FillPortion(1)).into(),
+                            Text::new(format!("{}层/启停{}次/换向{}次/开门{}次",
+                                              lift.wear.floors_travelled, lift.wear.starts,
+                                              lift.wear.reversals, lift.wear.door_cycles))
+                                .width(Length::FillPortion(2))
+                                .into(),
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Text::new("忙/闲/维护:").width(Length::FillPortion(1)).into(),
+                            Text::new(format!("{:.0}/{:.0}/{:.0}秒，平均载荷{:.0}%",
+                                              lift.wear.busy, lift.wear.idle, lift.wear.maintaining,
+                                              lift.wear.average_load_factor() * 100.0))
+                                .width(Length::FillPortion(2))
+                                .into(),
+                        ]).spacing(10).padding(4).into(),
+                        Row::with_children(vec![
+                            Button::new(&mut lift.door_open_btn_state, Text::new("开门"))
+                                .on_press(AppMessage::ClickedBtnDoorOpen(lift.no))
+                                .into(),
+                            Button::new(&mut lift.door_close_btn_state, Text::new("关门"))
+                                .on_press(AppMessage::ClickedBtnDoorClose(lift.no))
+                                .into(),
+                            Button::new(&mut lift.maintain_btn_state,
+                                        Text::new(if lift.state == State::Maintaining { "结束维护" } else { "维护" }))
+                                .on_press(AppMessage::ClickedBtnMaintain(lift.no))
+                                .into(),
                         ], ).spacing(10).padding(4).into(),
                         Row::with_children(vec![
                             Text::new(lift
                                 .schedule_floors
                                 .iter()
-                                .map(|o| o.0.to_string())
+                                .map(|o| building.name(*o.0))
                                 .collect::<Vec<_>>().join(","))
                                 .width(Length::Fill)
                                 .color(Color::from_rgb8(51, 161, 255))
@@ -698,7 +949,15 @@ impl Application for ElevatorApp {
                 ]).into());
                 _rows
             });
-        Column::with_children(new_rows)
+        rows.push(Row::with_children(vec![
+            Column::with_children(lift_rows)
+                .spacing(10)
+                .width(Length::FillPortion(5))
+                .into(),
+            dashboard,
+        ]).spacing(10)
+            .into());
+        Column::with_children(rows)
             .spacing(10)
             .height(Length::Fill)
             .height(Length::Fill).into()
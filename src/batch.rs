@@ -0,0 +1,556 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::Mutex;
+use std::time::Instant;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use crate::building::building;
+use crate::config::config;
+use crate::conf::{TFloor, STEADY_STATE_BUCKET_IN_SECONDS, STEADY_STATE_TOLERANCE, STEADY_STATE_WINDOW};
+use crate::dispatch::Dispatch;
+use crate::lift::Wear;
+use crate::metrics::{CallRecord, Ride};
+use crate::passenger::{Behaviour, Passenger};
+use crate::profile::TrafficProfile;
+use crate::simulation::{Simulation, Timer};
+use crate::stats::{self, Summary};
+use crate::trace::TraceInput;
+
+// 无界面批量模拟的场景
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    // 电梯数量
+    pub lifts: usize,
+    pub profile: TrafficProfile,
+    // 客流强度，每 5 分钟到达的人数占大楼总人数的百分比
+    pub intensity: f64,
+    pub dispatch: Dispatch,
+    // 每次模拟的时长, 单位：秒
+    pub duration: f64,
+    pub behaviour: Behaviour,
+}
+
+// 事件队列里的事件
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EventKind {
+    // 电梯运行完一层楼
+    ArriveByOneFloor(usize),
+    // 电梯门的定时器到期：电梯序号，门的状态序号
+    DoorTimer(usize, u32),
+    // 自动客流的乘客到达
+    TrafficArrival,
+    // 每秒看看有没有人要改走楼梯
+    PassengerTick,
+    // 兜底调度
+    Scheduling,
+}
+
+pub struct Event {
+    // 模拟时钟, 单位：秒
+    pub at: f64,
+    // 同一时刻的事件按加入的顺序处理
+    seq: u64,
+    pub kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// BinaryHeap 是大顶堆，反过来比较，最早的事件先出来
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at
+            .total_cmp(&self.at)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+// 按事件推进模拟时钟，不用真的等待
+pub struct EventQueue {
+    heap: BinaryHeap<Event>,
+    seq: u64,
+    // 每部电梯最新的运行、开关门定时器事件的序号
+    // 新的定时器替换掉还没到期的旧定时器，和界面里的电梯任务一样，旧的出队时丢掉
+    running: HashMap<usize, u64>,
+    door: HashMap<usize, u64>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            seq: 0,
+            running: HashMap::new(),
+            door: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, at: f64, kind: EventKind) {
+        self.seq += 1;
+        self.heap.push(Event { at, seq: self.seq, kind });
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        self.drop_superseded();
+        let event = self.heap.pop()?;
+        match event.kind {
+            EventKind::ArriveByOneFloor(no) => {
+                self.running.remove(&no);
+            }
+            EventKind::DoorTimer(no, _) => {
+                self.door.remove(&no);
+            }
+            _ => {}
+        }
+        Some(event)
+    }
+
+    // 最早的事件的时刻
+    pub fn peek_at(&mut self) -> Option<f64> {
+        self.drop_superseded();
+        self.heap.peek().map(|e| e.at)
+    }
+
+    // 丢掉队首已经被新定时器替换掉的事件
+    fn drop_superseded(&mut self) {
+        while let Some(event) = self.heap.peek() {
+            let latest = match event.kind {
+                EventKind::ArriveByOneFloor(no) => self.running.get(&no),
+                EventKind::DoorTimer(no, _) => self.door.get(&no),
+                _ => break,
+            };
+            if latest == Some(&event.seq) {
+                break;
+            }
+            self.heap.pop();
+        }
+    }
+
+    // 把模拟要的定时器放进队列
+    pub fn push_timers(&mut self, sim: &mut Simulation) {
+        let now = sim.clock;
+        for timer in sim.take_timers() {
+            match timer {
+                Timer::RunOneFloor(no, millis) => {
+                    self.push(now + millis as f64 / 1000.0, EventKind::ArriveByOneFloor(no));
+                    self.running.insert(no, self.seq);
+                }
+                Timer::Door(no, millis, seq) => {
+                    self.push(now + millis as f64 / 1000.0, EventKind::DoorTimer(no, seq));
+                    self.door.insert(no, self.seq);
+                }
+            }
+        }
+    }
+
+    // 安排自动客流下一位乘客的到达
+    pub fn push_traffic(&mut self, sim: &Simulation) {
+        if let Some(at) = sim.next_arrival_at() {
+            self.push(at, EventKind::TrafficArrival);
+        }
+    }
+
+    // 处理一个事件，把它引出的下一个事件和模拟要的定时器放进队列
+    // 事件和界面里的消息一样，都交给 Simulation::apply_input 处理
+    pub fn handle(&mut self, sim: &mut Simulation, event: &Event) {
+        sim.advance(event.at);
+        match event.kind {
+            EventKind::ArriveByOneFloor(no) => sim.apply_input(&TraceInput::ArriveByOneFloor(no)),
+            EventKind::DoorTimer(no, seq) => sim.apply_input(&TraceInput::DoorTimer(no, seq)),
+            EventKind::TrafficArrival => {
+                // 客流关掉或者换过了的话，旧生成器的到达事件作废
+                if sim.next_arrival_at() == Some(event.at) {
+                    sim.apply_input(&TraceInput::TrafficArrival);
+                    self.push_traffic(sim);
+                }
+            }
+            EventKind::PassengerTick => {
+                sim.apply_input(&TraceInput::PassengerTick);
+                self.push(event.at + 1.0, EventKind::PassengerTick);
+            }
+            EventKind::Scheduling => {
+                sim.apply_input(&TraceInput::Scheduling);
+                self.push(event.at + config().scheduling_fallback as f64, EventKind::Scheduling);
+            }
+        }
+        self.push_timers(sim);
+    }
+}
+
+// 一次模拟的结果
+#[derive(Clone, Debug)]
+pub struct Replication {
+    pub seed: u64,
+    // 模拟的时长，以及不计入统计的预热时长, 单位：秒
+    pub duration: f64,
+    pub warmup: f64,
+    // 已经到达目的楼层（包括走楼梯）的乘客
+    pub finished: Vec<Passenger>,
+    // 模拟结束时还没到达的乘客到达候梯厅的时刻
+    pub unfinished_arrivals: Vec<f64>,
+    // 应答了的呼叫，包括预热期的
+    pub calls: Vec<CallRecord>,
+    // 每部电梯的楼层变化：到达楼层的时刻和楼层，画运行图用
+    pub positions: Vec<Vec<(f64, TFloor)>>,
+    // 模拟结束时每部电梯的使用和磨损统计
+    pub wear: Vec<Wear>,
+    // 乘电梯到达目的楼层的人数
+    pub delivered: usize,
+    // 改走楼梯的人数
+    pub took_stairs: usize,
+    // 模拟结束时还没到达的人数
+    pub unfinished: usize,
+    // 每位到达的乘客的候梯时间、行程时间（从到达候梯厅到走出轿厢）, 单位：秒
+    // 和导出的行程记录一样按 Ride 算：候梯时间从到达候梯厅到接他的电梯到站
+    pub waiting_times: Vec<f64>,
+    pub journey_times: Vec<f64>,
+    // 每小时运送的人数
+    pub throughput: f64,
+}
+
+impl Replication {
+    // 只统计预热期之后到达的乘客，运送能力按预热期之后走出轿厢的人数算
+    fn new(seed: u64, duration: f64, finished: Vec<Passenger>, unfinished_arrivals: Vec<f64>, calls: Vec<CallRecord>,
+           positions: Vec<Vec<(f64, TFloor)>>, warmup: f64) -> Self {
+        let delivered = finished
+            .iter()
+            .filter(|p| !p.took_stairs && p.arrived_at >= warmup)
+            .collect::<Vec<_>>();
+        let rides = delivered
+            .iter()
+            .filter_map(|p| Ride::of(p))
+            .collect::<Vec<_>>();
+        let alighted = finished
+            .iter()
+            .filter(|p| p.alighted_at.map_or(false, |t| t >= warmup))
+            .count();
+        Self {
+            seed,
+            duration,
+            warmup,
+            delivered: delivered.len(),
+            took_stairs: finished
+                .iter()
+                .filter(|p| p.took_stairs && p.arrived_at >= warmup)
+                .count(),
+            unfinished: unfinished_arrivals
+                .iter()
+                .filter(|at| **at >= warmup)
+                .count(),
+            waiting_times: rides.iter().map(|r| r.waiting).collect(),
+            journey_times: rides.iter().map(|r| r.total).collect(),
+            throughput: alighted as f64 * 3600.0 / (duration - warmup),
+            finished,
+            unfinished_arrivals,
+            calls,
+            positions,
+            wear: vec![],
+        }
+    }
+
+    // 去掉预热期后重新统计
+    pub fn after(&self, warmup: f64) -> Self {
+        Self {
+            wear: self.wear.clone(),
+            ..Self::new(self.seed, self.duration, self.finished.clone(), self.unfinished_arrivals.clone(), self.calls.clone(), self.positions.clone(), warmup)
+        }
+    }
+
+    // 每个时间段里被电梯接走的乘客的平均候梯时间，没有人被接走的时间段为 0
+    // 按电梯到站接人的时刻分段，模拟结束时还在候梯的人不会让最后几段偏低
+    pub fn waiting_series(&self, bucket: f64) -> Vec<f64> {
+        let n = (self.duration / bucket).ceil() as usize;
+        let mut sums = vec![(0.0, 0usize); n];
+        for ride in self.finished.iter().filter_map(Ride::of) {
+            let k = ((ride.picked_up_at / bucket) as usize).min(n - 1);
+            sums[k].0 += ride.waiting;
+            sums[k].1 += 1;
+        }
+        sums.into_iter()
+            .map(|(sum, count)| if count == 0 { 0.0 } else { sum / count as f64 })
+            .collect()
+    }
+}
+
+// 用给定的随机种子跑一次模拟
+// 客流和电梯的初始楼层都由种子决定，同样的种子结果完全一样
+pub fn run_replication(scenario: &Scenario, seed: u64) -> Replication {
+    let mut sim = Simulation::new(0.0);
+    sim.verbose = false;
+    sim.behaviour = scenario.behaviour;
+    sim.dispatch = scenario.dispatch;
+    sim.set_epoch(Instant::now(), 0.0);
+    // 电梯的初始楼层用另一条随机数流，不影响客流
+    let mut rng = ChaCha12Rng::seed_from_u64(seed);
+    rng.set_stream(1);
+    let building = building();
+    for _ in 0..scenario.lifts {
+        let no = sim.add_lift();
+        let lift = &mut sim.lifts[no];
+        lift.cur_floor = lift.random_floor(&mut rng);
+        lift.position = building.position(lift.cur_floor);
+    }
+    let mut positions = sim.lifts
+        .iter()
+        .map(|lift| vec![(0.0, lift.cur_floor)])
+        .collect::<Vec<_>>();
+    sim.start_traffic(scenario.profile, scenario.intensity, seed);
+
+    let mut queue = EventQueue::new();
+    queue.push_traffic(&sim);
+    queue.push(1.0, EventKind::PassengerTick);
+    queue.push(config().scheduling_fallback as f64, EventKind::Scheduling);
+    while let Some(event) = queue.pop() {
+        if event.at > scenario.duration {
+            break;
+        }
+        queue.handle(&mut sim, &event);
+        for (lift, floors) in sim.lifts.iter().zip(positions.iter_mut()) {
+            if floors.last().map(|(_, floor)| *floor) != Some(lift.cur_floor) {
+                floors.push((event.at, lift.cur_floor));
+            }
+        }
+    }
+
+    let unfinished = sim.passengers
+        .waiting_passengers()
+        .chain(sim.lifts.iter().flat_map(|lift| lift.riders.iter()))
+        .map(|p| p.arrived_at)
+        .collect();
+    // 把最后一个事件到模拟结束的时间也算进去
+    sim.advance(scenario.duration);
+    let calls = sim.metrics.take_calls();
+    Replication {
+        wear: sim.lifts.iter().map(|lift| lift.wear).collect(),
+        ..Replication::new(seed, scenario.duration, std::mem::take(&mut sim.passengers.finished), unfinished, calls, positions, 0.0)
+    }
+}
+
+// 预热期的处理
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warmup {
+    // 固定的预热时长, 单位：秒
+    Fixed(f64),
+    // 用移动平均法自动找到进入稳态的时刻
+    Auto,
+}
+
+// 去掉预热期，返回预热时长和重新统计的结果
+// 自动检测时，把各次模拟按时间段的平均候梯时间先对齐求平均，再找进入稳态的时刻（Welch 方法）
+// 几组结果（比如对比的几种调度策略）用同一个预热时长，才能配对比较
+pub fn apply_warmup(groups: &[Vec<Replication>], warmup: Warmup) -> (f64, Vec<Vec<Replication>>) {
+    let warmup = match warmup {
+        Warmup::Fixed(warmup) => warmup,
+        Warmup::Auto => {
+            let duration = groups[0][0].duration;
+            let detected = groups
+                .iter()
+                .map(|rs| {
+                    let series = rs
+                        .iter()
+                        .map(|r| r.waiting_series(STEADY_STATE_BUCKET_IN_SECONDS))
+                        .collect::<Vec<_>>();
+                    let averaged = (0..series[0].len())
+                        .map(|k| stats::mean(&series.iter().map(|s| s[k]).collect::<Vec<_>>()))
+                        .collect::<Vec<_>>();
+                    stats::steady_state_start(&averaged, STEADY_STATE_WINDOW, STEADY_STATE_TOLERANCE)
+                        .map(|k| k as f64 * STEADY_STATE_BUCKET_IN_SECONDS)
+                })
+                .collect::<Option<Vec<_>>>();
+            match detected {
+                Some(starts) => starts.into_iter().fold(0.0, f64::max),
+                None => {
+                    println!("没有检测到稳态，可能要加长模拟时长，先去掉前一半的时间");
+                    duration / 2.0
+                }
+            }
+        }
+    };
+    let groups = groups
+        .iter()
+        .map(|rs| rs.iter().map(|r| r.after(warmup)).collect())
+        .collect();
+    (warmup, groups)
+}
+
+// 用 n 个不同的随机种子（base_seed, base_seed + 1, ...）重复模拟，分到 threads 个线程上并行
+// 结果按种子的顺序排列，和线程数无关
+pub fn run_batch(scenario: &Scenario, n: usize, base_seed: u64, threads: usize) -> Vec<Replication> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; n]);
+    std::thread::scope(|s| {
+        for _ in 0..threads.clamp(1, n.max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, AtomicOrdering::Relaxed);
+                if i >= n {
+                    break;
+                }
+                let r = run_replication(scenario, base_seed.wrapping_add(i as u64));
+                results.lock().unwrap()[i] = Some(r);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+// 每次模拟算出的指标：名称，单位，取值
+pub const KPIS: [(&str, &str, fn(&Replication) -> f64); 7] = [
+    ("平均候梯时间", "秒", |r| stats::mean(&r.waiting_times)),
+    ("候梯时间P95", "秒", |r| stats::percentile(&r.waiting_times, 95.0)),
+    ("平均行程时间", "秒", |r| stats::mean(&r.journey_times)),
+    ("行程时间P95", "秒", |r| stats::percentile(&r.journey_times, 95.0)),
+    ("运送能力", "人/小时", |r| r.throughput),
+    ("走楼梯", "人", |r| r.took_stairs as f64),
+    ("未到达", "人", |r| r.unfinished as f64),
+];
+
+// 批量模拟的汇总：每个指标先在每次模拟里算出一个值，再对各次模拟的值做统计
+pub struct BatchReport {
+    pub replications: usize,
+    // 指标名称，单位，汇总
+    pub rows: Vec<(&'static str, &'static str, Summary)>,
+}
+
+impl BatchReport {
+    pub fn new(results: &[Replication]) -> Self {
+        Self {
+            replications: results.len(),
+            rows: KPIS
+                .iter()
+                .map(|(name, unit, kpi)| (*name, *unit, Summary::of(&results.iter().map(kpi).collect::<Vec<_>>())))
+                .collect(),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("{:<12}{:>10}{:>20}{:>10}{:>10}{:>10}{:>10}",
+                 "指标", "均值", "95%置信区间", "标准差", "P5", "P50", "P95");
+        for (name, unit, s) in &self.rows {
+            println!("{:<12}{:>10.2}{:>20}{:>10.2}{:>10.2}{:>10.2}{:>10.2}",
+                     format!("{}({})", name, unit),
+                     s.mean,
+                     format!("[{:.2}, {:.2}]", s.mean - s.ci95, s.mean + s.ci95),
+                     s.std_dev, s.p5, s.p50, s.p95);
+        }
+    }
+}
+
+// 多种调度策略在同样客流下的对比，第一种策略是基准
+// 每个种子下各策略的乘客完全一样，所以按种子配对比较差值，比各自独立比较灵敏得多
+pub struct ComparisonReport {
+    pub strategies: Vec<Dispatch>,
+    // 指标名称，单位，每种策略的汇总，每种非基准策略和基准的差值的汇总
+    pub rows: Vec<(&'static str, &'static str, Vec<Summary>, Vec<Summary>)>,
+}
+
+impl ComparisonReport {
+    // results 和 strategies 一一对应，每组结果都按种子排列
+    pub fn new(strategies: &[Dispatch], results: &[Vec<Replication>]) -> Self {
+        let rows = KPIS
+            .iter()
+            .map(|(name, unit, kpi)| {
+                let values = results
+                    .iter()
+                    .map(|rs| rs.iter().map(kpi).collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+                let diffs = values[1..]
+                    .iter()
+                    .map(|vs| Summary::of(&vs
+                        .iter()
+                        .zip(values[0].iter())
+                        .map(|(v, base)| v - base)
+                        .collect::<Vec<_>>()))
+                    .collect();
+                (*name, *unit, values.iter().map(|vs| Summary::of(vs)).collect(), diffs)
+            })
+            .collect();
+        Self {
+            strategies: strategies.to_vec(),
+            rows,
+        }
+    }
+
+    pub fn print(&self) {
+        let base = self.strategies[0];
+        print!("{:<12}", "指标");
+        self.strategies.iter().for_each(|d| print!("{:>20}", d.to_string()));
+        self.strategies[1..].iter().for_each(|d| print!("{:>30}", format!("{}-{}", d, base)));
+        println!();
+        for (name, unit, summaries, diffs) in &self.rows {
+            print!("{:<12}", format!("{}({})", name, unit));
+            summaries.iter().for_each(|s| print!("{:>20}", format!("{:.2}±{:.2}", s.mean, s.ci95)));
+            // 差值的 95% 置信区间不包含 0 时，差别是显著的
+            diffs.iter().for_each(|s| print!("{:>30}", format!("{:+.2} [{:+.2}, {:+.2}] {}",
+                                                                 s.mean, s.mean - s.ci95, s.mean + s.ci95,
+                                                                 if s.mean.abs() > s.ci95 { "显著" } else { "不显著" })));
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(queue: &mut EventQueue) -> Vec<(f64, EventKind)> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|e| (e.at, e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn earliest_first_then_in_order() {
+        let mut queue = EventQueue::new();
+        queue.push(2.0, EventKind::Scheduling);
+        queue.push(1.0, EventKind::PassengerTick);
+        queue.push(1.0, EventKind::TrafficArrival);
+        assert_eq!(queue.peek_at(), Some(1.0));
+        assert_eq!(kinds(&mut queue), vec![
+            (1.0, EventKind::PassengerTick),
+            (1.0, EventKind::TrafficArrival),
+            (2.0, EventKind::Scheduling),
+        ]);
+    }
+
+    #[test]
+    fn new_timers_replace_pending_ones() {
+        let mut sim = Simulation::new(0.0);
+        sim.verbose = false;
+        let mut queue = EventQueue::new();
+        sim.push_timer(Timer::RunOneFloor(0, 3000));
+        sim.push_timer(Timer::Door(0, 1000, 1));
+        sim.push_timer(Timer::Door(1, 1000, 1));
+        queue.push_timers(&mut sim);
+        // 和界面里的电梯任务一样，同一部电梯新的定时器替换掉还没到期的
+        sim.push_timer(Timer::RunOneFloor(0, 5000));
+        sim.push_timer(Timer::Door(0, 2000, 2));
+        queue.push_timers(&mut sim);
+        assert_eq!(queue.peek_at(), Some(1.0));
+        assert_eq!(kinds(&mut queue), vec![
+            (1.0, EventKind::DoorTimer(1, 1)),
+            (2.0, EventKind::DoorTimer(0, 2)),
+            (5.0, EventKind::ArriveByOneFloor(0)),
+        ]);
+        // 到期之后再安排的定时器不受影响
+        sim.push_timer(Timer::Door(0, 1000, 3));
+        queue.push_timers(&mut sim);
+        assert_eq!(kinds(&mut queue), vec![(1.0, EventKind::DoorTimer(0, 3))]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::sync::{Arc, RwLock};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::conf::{FLOOR_HEIGHT_IN_METERS, MAX_FLOOR, MIN_FLOOR, SKIP_FLOORS, TFloor};
+use crate::floor_btn::Direction;
+
+// 楼层
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Floor {
+    // 楼层号，乘客、按钮和调度都用楼层号表示楼层
+    pub number: TFloor,
+    // 显示的名称
+    pub name: String,
+    // 楼层地面的高度, 单位：米； 最低楼层的高度为 0
+    pub height: f32,
+}
+
+// 大楼：从低到高排列的楼层，楼层在列表里的位置就是楼层的索引
+// 电梯按索引一层层地运行，楼层号不用连续
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Building {
+    floors: Vec<Floor>,
+}
+
+impl Building {
+    // 楼层要按从低到高排列，楼层号和高度都要递增
+    pub fn new(floors: Vec<Floor>) -> Result<Self, String> {
+        if floors.len() < 2 {
+            return Err(format!("大楼至少要有两层，现在只有{}层", floors.len()));
+        }
+        for w in floors.windows(2) {
+            if w[1].number <= w[0].number {
+                return Err(format!("楼层号要从低到高递增: {} 排在 {} 后面", w[1].number, w[0].number));
+            }
+            if !(w[1].height > w[0].height) {
+                return Err(format!("楼层的高度要从低到高递增: {}层({}米) 不比 {}层({}米) 高",
+                                   w[1].number, w[1].height, w[0].number, w[0].height));
+            }
+        }
+        Ok(Self { floors })
+    }
+
+    // 从 lowest 层到 highest 层，每层一样高
+    pub fn uniform(lowest: TFloor, highest: TFloor, floor_height: f32) -> Result<Self, String> {
+        Self::uniform_skipping(lowest, highest, &[], floor_height)
+    }
+
+    // 和 uniform 一样，但是没有 skip 里的楼层，比如不吉利的 4、13、14 层
+    pub fn uniform_skipping(lowest: TFloor, highest: TFloor, skip: &[TFloor], floor_height: f32) -> Result<Self, String> {
+        let floors = (lowest..=highest)
+            .filter(|f| !skip.contains(f))
+            .enumerate()
+            .map(|(idx, number)| Floor {
+                number,
+                name: number.to_string(),
+                height: idx as f32 * floor_height,
+            })
+            .collect();
+        Self::new(floors)
+    }
+
+    // 默认的大楼
+    pub fn standard() -> Self {
+        Self::uniform_skipping(MIN_FLOOR, MAX_FLOOR, &SKIP_FLOORS, FLOOR_HEIGHT_IN_METERS).unwrap()
+    }
+
+    pub fn floors(&self) -> &[Floor] {
+        &self.floors
+    }
+
+    pub fn len(&self) -> usize {
+        self.floors.len()
+    }
+
+    // 所有楼层号，从低到高
+    pub fn numbers(&self) -> Vec<TFloor> {
+        self.floors.iter().map(|f| f.number).collect()
+    }
+
+    pub fn lowest(&self) -> TFloor {
+        self.floors[0].number
+    }
+
+    pub fn highest(&self) -> TFloor {
+        self.floors[self.floors.len() - 1].number
+    }
+
+    // 楼层号对应的索引
+    pub fn index(&self, floor: TFloor) -> Option<usize> {
+        self.floors.binary_search_by_key(&floor, |f| f.number).ok()
+    }
+
+    // 索引对应的楼层号，超出范围时取最近的楼层
+    pub fn floor_at(&self, idx: usize) -> TFloor {
+        self.floors[idx.min(self.floors.len() - 1)].number
+    }
+
+    pub fn get(&self, floor: TFloor) -> Option<&Floor> {
+        self.index(floor).map(|idx| &self.floors[idx])
+    }
+
+    // 某方向上的下一层楼，已经到顶或者到底时返回 None
+    pub fn next(&self, floor: TFloor, direction: Direction) -> Option<TFloor> {
+        let idx = self.index(floor)?;
+        let next = match direction {
+            Direction::Up => idx + 1,
+            Direction::Down => idx.checked_sub(1)?,
+        };
+        self.floors.get(next).map(|f| f.number)
+    }
+
+    // 楼层所在的高度, 单位：米
+    pub fn position(&self, floor: TFloor) -> f32 {
+        self.get(floor)
+            .map(|f| f.height)
+            .unwrap_or_else(|| panic!("大楼里没有{}层", floor))
+    }
+
+    // 两个楼层之间隔了几层
+    pub fn distance(&self, from: TFloor, to: TFloor) -> u32 {
+        match (self.index(from), self.index(to)) {
+            (Some(a), Some(b)) => (a as i64 - b as i64).unsigned_abs() as u32,
+            _ => 0,
+        }
+    }
+
+    // 楼层显示的名称
+    pub fn name(&self, floor: TFloor) -> String {
+        self.get(floor)
+            .map(|f| f.name.clone())
+            .unwrap_or_else(|| floor.to_string())
+    }
+
+    // 设置楼层显示的名称
+    pub fn set_name(&mut self, floor: TFloor, name: String) -> Result<(), String> {
+        let idx = self.index(floor).ok_or_else(|| format!("大楼里没有{}层", floor))?;
+        self.floors[idx].name = name;
+        Ok(())
+    }
+
+    // 随机选一个楼层
+    pub fn random_floor<R: Rng>(&self, rng: &mut R) -> TFloor {
+        self.floors[rng.gen_range(0..self.floors.len())].number
+    }
+
+    // 从 origin 楼层出发，往 direction 方向随机选一个目的楼层
+    pub fn random_destination<R: Rng>(&self, origin: TFloor, direction: Direction, rng: &mut R) -> Option<TFloor> {
+        let idx = self.index(origin)?;
+        let range = match direction {
+            Direction::Up => idx + 1..self.floors.len(),
+            Direction::Down => 0..idx,
+        };
+        if range.is_empty() {
+            return None;
+        }
+        Some(self.floors[rng.gen_range(range)].number)
+    }
+}
+
+lazy_static! {
+    // 当前的大楼
+    static ref BUILDING: RwLock<Arc<Building>> = RwLock::new(Arc::new(Building::standard()));
+}
+
+// 当前的大楼
+pub fn building() -> Arc<Building> {
+    BUILDING.read().unwrap().clone()
+}
+
+// 替换当前的大楼，要在创建电梯之前调用
+pub fn set_building(building: Building) {
+    *BUILDING.write().unwrap() = Arc::new(building);
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use crate::batch::Replication;
+use crate::building::building;
+use crate::conf::{CHART_SIZE, STEADY_STATE_BUCKET_IN_SECONDS, TFloor, WAITING_HISTOGRAM_BIN_IN_SECONDS};
+
+// 图表的文件格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChartFormat {
+    Svg,
+    Png,
+}
+
+impl Default for ChartFormat {
+    fn default() -> Self {
+        ChartFormat::Svg
+    }
+}
+
+impl ChartFormat {
+    pub const ALL: [ChartFormat; 2] = [ChartFormat::Svg, ChartFormat::Png];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChartFormat::Svg => "svg",
+            ChartFormat::Png => "png",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+}
+
+impl fmt::Display for ChartFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// SVG 里的文字由看图的程序找字体显示，转换成 PNG 时用界面的字体，不依赖系统里装的字体
+const FONT: &str = "sans-serif";
+
+// 同一个画图函数都先画成 SVG，PNG 再从 SVG 转换
+macro_rules! render {
+    ($format:expr, $path:expr, $draw:ident($($arg:expr),*)) => {
+        match $format {
+            ChartFormat::Svg => $draw(SVGBackend::new(&$path, CHART_SIZE).into_drawing_area(), $($arg),*),
+            ChartFormat::Png => {
+                let mut svg = String::new();
+                $draw(SVGBackend::with_string(&mut svg, CHART_SIZE).into_drawing_area(), $($arg),*)
+                    .and_then(|_| save_png(&svg, &$path))
+            }
+        }
+    };
+}
+
+// 把画好的 SVG 转换成 PNG 保存
+fn save_png(svg: &str, path: &str) -> Result<(), String> {
+    let mut opt = usvg::Options::default();
+    opt.fontdb.load_font_data(include_bytes!("../assets/font/ZiTiGuanJiaFangSongTi-2.ttf").to_vec());
+    if let Some(family) = opt.fontdb.faces().first().map(|face| face.family.clone()) {
+        opt.fontdb.set_sans_serif_family(family);
+    }
+    let tree = usvg::Tree::from_str(svg, &opt.to_ref())
+        .map_err(|e| format!("图表{}转换成 PNG 失败: {}", path, e))?;
+    let mut pixmap = tiny_skia::Pixmap::new(CHART_SIZE.0, CHART_SIZE.1).unwrap();
+    resvg::render(&tree, usvg::FitTo::Original, tiny_skia::Transform::default(), pixmap.as_mut())
+        .ok_or_else(|| format!("图表{}转换成 PNG 失败", path))?;
+    pixmap.save_png(path).map_err(|e| format!("图表{}保存失败: {}", path, e))
+}
+
+// 用批量模拟的结果画图，保存到 dir 目录，返回生成的文件
+// 候梯时间和呼叫次数去掉预热期，运行图只画第一次模拟
+pub fn render_all(dir: &str, format: ChartFormat, results: &[Replication]) -> Result<Vec<String>, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("图表目录{}创建失败: {}", dir, e))?;
+    let path = |name: &str| format!("{}/{}.{}", dir.trim_end_matches('/'), name, format);
+    let mut files = vec![];
+
+    let file = path("waiting_histogram");
+    render!(format, file, waiting_histogram(results))?;
+    files.push(file);
+
+    let file = path("waiting_over_time");
+    render!(format, file, waiting_over_time(results))?;
+    files.push(file);
+
+    let file = path("calls_per_floor");
+    render!(format, file, calls_per_floor(results))?;
+    files.push(file);
+
+    if let Some(first) = results.first() {
+        let file = path("lift_positions");
+        render!(format, file, lift_positions(first))?;
+        files.push(file);
+    }
+    Ok(files)
+}
+
+fn draw_err<E: std::error::Error + Send + Sync>(e: DrawingAreaErrorKind<E>) -> String {
+    format!("画图失败: {}", e)
+}
+
+// 候梯时间的分布，所有模拟合起来
+fn waiting_histogram<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, results: &[Replication]) -> Result<(), String>
+    where DB::ErrorType: 'static {
+    let waiting = results
+        .iter()
+        .flat_map(|r| r.waiting_times.iter().copied())
+        .collect::<Vec<_>>();
+    let bins = waiting
+        .iter()
+        .map(|w| (w / WAITING_HISTOGRAM_BIN_IN_SECONDS) as usize)
+        .fold(BTreeMap::<usize, usize>::new(), |mut bins, k| {
+            *bins.entry(k).or_default() += 1;
+            bins
+        });
+    let max_bin = bins.keys().last().copied().unwrap_or(0) + 1;
+    let max_count = bins.values().copied().max().unwrap_or(0).max(1);
+
+    root.fill(&WHITE).map_err(draw_err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("候梯时间分布（{}人）", waiting.len()), (FONT, 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..max_bin as f64 * WAITING_HISTOGRAM_BIN_IN_SECONDS, 0..max_count + max_count / 10 + 1)
+        .map_err(draw_err)?;
+    chart.configure_mesh()
+        .x_desc("候梯时间(秒)")
+        .y_desc("人数")
+        .label_style((FONT, 16))
+        .disable_x_mesh()
+        .draw()
+        .map_err(draw_err)?;
+    chart.draw_series(bins.iter().map(|(k, count)| {
+        let x0 = *k as f64 * WAITING_HISTOGRAM_BIN_IN_SECONDS;
+        Rectangle::new([(x0, 0), (x0 + WAITING_HISTOGRAM_BIN_IN_SECONDS, *count)], BLUE.mix(0.6).filled())
+    })).map_err(draw_err)?;
+    root.present().map_err(draw_err)
+}
+
+// 每个时间段里被电梯接走的乘客的平均候梯时间，取所有模拟的平均
+fn waiting_over_time<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, results: &[Replication]) -> Result<(), String>
+    where DB::ErrorType: 'static {
+    let bucket = STEADY_STATE_BUCKET_IN_SECONDS;
+    let series = results
+        .iter()
+        .map(|r| r.waiting_series(bucket))
+        .collect::<Vec<_>>();
+    let n = series.iter().map(|s| s.len()).max().unwrap_or(0);
+    let points = (0..n)
+        .map(|k| {
+            let values = series.iter().filter_map(|s| s.get(k)).collect::<Vec<_>>();
+            let mean = values.iter().copied().sum::<f64>() / values.len() as f64;
+            ((k as f64 + 0.5) * bucket / 60.0, mean)
+        })
+        .collect::<Vec<_>>();
+    let duration = results.first().map_or(0.0, |r| r.duration) / 60.0;
+    let warmup = results.first().map_or(0.0, |r| r.warmup) / 60.0;
+    let max_waiting = points.iter().map(|(_, w)| *w).fold(1.0, f64::max);
+
+    root.fill(&WHITE).map_err(draw_err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("平均候梯时间的变化", (FONT, 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..duration.max(1.0), 0.0..max_waiting * 1.1)
+        .map_err(draw_err)?;
+    chart.configure_mesh()
+        .x_desc("模拟时间(分钟)")
+        .y_desc("候梯时间(秒)")
+        .label_style((FONT, 16))
+        .draw()
+        .map_err(draw_err)?;
+    if warmup > 0.0 {
+        // 预热期的结束
+        chart.draw_series(LineSeries::new(vec![(warmup, 0.0), (warmup, max_waiting * 1.1)], &RED))
+            .map_err(draw_err)?;
+    }
+    chart.draw_series(LineSeries::new(points, &BLUE)).map_err(draw_err)?;
+    root.present().map_err(draw_err)
+}
+
+// 每层每次模拟平均的呼叫次数，上行、下行分开
+fn calls_per_floor<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, results: &[Replication]) -> Result<(), String>
+    where DB::ErrorType: 'static {
+    use crate::floor_btn::Direction;
+    let building = building();
+    let runs = results.len().max(1) as f64;
+    let mut counts = BTreeMap::<(usize, Direction), f64>::new();
+    for r in results {
+        for call in r.calls.iter().filter(|c| c.called_at >= r.warmup) {
+            if let Some(idx) = building.index(call.floor) {
+                *counts.entry((idx, call.direction)).or_default() += 1.0 / runs;
+            }
+        }
+    }
+    let max_count = counts.values().copied().fold(1.0, f64::max);
+    let floors = building.len();
+
+    root.fill(&WHITE).map_err(draw_err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption("各楼层的呼叫次数", (FONT, 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..floors as f64, 0.0..max_count * 1.1)
+        .map_err(draw_err)?;
+    chart.configure_mesh()
+        .x_desc("楼层")
+        .y_desc("每次模拟的呼叫次数")
+        .label_style((FONT, 16))
+        .x_labels(floors.min(25))
+        .x_label_formatter(&|x| floor_name(*x))
+        .disable_x_mesh()
+        .draw()
+        .map_err(draw_err)?;
+    for (direction, offset, color) in [(Direction::Up, 0.1, BLUE), (Direction::Down, 0.5, RED)] {
+        chart.draw_series(counts
+            .iter()
+            .filter(|((_, d), _)| *d == direction)
+            .map(|((idx, _), count)| {
+                let x0 = *idx as f64 + offset;
+                Rectangle::new([(x0, 0.0), (x0 + 0.4, *count)], color.mix(0.7).filled())
+            }))
+            .map_err(draw_err)?
+            .label(format!("{}行", direction))
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], color.filled()));
+    }
+    chart.configure_series_labels()
+        .label_font((FONT, 16))
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()
+        .map_err(draw_err)?;
+    root.present().map_err(draw_err)
+}
+
+// 电梯运行图：每部电梯所在的楼层随时间的变化
+fn lift_positions<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, r: &Replication) -> Result<(), String>
+    where DB::ErrorType: 'static {
+    let building = building();
+    let floors = building.len();
+    let duration = r.duration / 60.0;
+
+    root.fill(&WHITE).map_err(draw_err)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("电梯运行图（随机种子{}）", r.seed), (FONT, 28))
+        .margin(20)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..duration, -0.5..floors as f64 - 0.5)
+        .map_err(draw_err)?;
+    chart.configure_mesh()
+        .x_desc("模拟时间(分钟)")
+        .y_desc("楼层")
+        .label_style((FONT, 16))
+        .y_labels(floors.min(25))
+        .y_label_formatter(&|y| floor_name(*y + 0.5))
+        .draw()
+        .map_err(draw_err)?;
+    for (no, positions) in r.positions.iter().enumerate() {
+        let color = Palette99::pick(no).to_rgba();
+        // 到达下一个楼层之前一直在上一个楼层
+        let points = positions
+            .iter()
+            .zip(positions.iter().skip(1).map(|(at, _)| *at).chain(std::iter::once(r.duration)))
+            .flat_map(|((at, floor), until)| {
+                let y = building.index(*floor).unwrap_or(0) as f64;
+                [(*at / 60.0, y), (until / 60.0, y)]
+            })
+            .collect::<Vec<_>>();
+        chart.draw_series(LineSeries::new(points, &color))
+            .map_err(draw_err)?
+            .label(format!("电梯#{}", no + 1))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+    }
+    chart.configure_series_labels()
+        .label_font((FONT, 16))
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()
+        .map_err(draw_err)?;
+    root.present().map_err(draw_err)
+}
+
+// 坐标轴上按楼层的序号显示楼层的名称
+fn floor_name(x: f64) -> String {
+    let building = building();
+    let idx = x.floor();
+    if idx < 0.0 || idx as usize >= building.len() {
+        return String::new();
+    }
+    let floor: TFloor = building.floor_at(idx as usize);
+    building.name(floor)
+}
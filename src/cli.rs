@@ -0,0 +1,272 @@
+use crate::batch::Warmup;
+use crate::charts::ChartFormat;
+use crate::conf::{BATCH_DURATION_IN_SECONDS, CALLS_FILE, SNAPSHOT_FILE, TRIPS_FILE, WARMUP_IN_SECONDS, WARMUP_MAX_RATIO, WEAR_FILE};
+use crate::dispatch::Dispatch;
+use crate::profile::TrafficProfile;
+
+// 命令行参数
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    // 配置文件，不指定时用默认配置
+    pub config: Option<String>,
+    // 启动后自动开启的客流
+    pub profile: Option<TrafficProfile>,
+    // 客流强度，每 5 分钟到达的人数占大楼总人数的百分比，不指定时用配置里的
+    pub intensity: Option<f64>,
+    // 客流的随机种子，不指定时随机选一个
+    pub seed: Option<u64>,
+    // 调度策略
+    pub dispatch: Dispatch,
+    // 保存、载入快照使用的文件
+    pub snapshot: String,
+    // 启动后马上载入快照
+    pub load: bool,
+    // 导出乘客行程、呼叫记录、电梯磨损统计的文件，批量模拟结束后自动导出
+    pub trips: String,
+    pub calls: String,
+    pub wear: String,
+    // 把所有输入（界面操作和定时器到期）录制到轨迹文件
+    pub record: Option<String>,
+    // 不打开窗口，按录制的时刻回放轨迹文件
+    pub replay: Option<String>,
+    // 不打开窗口，用不同的随机种子批量模拟多少次
+    pub batch: Option<usize>,
+    // 批量模拟时每次模拟的时长, 单位：秒
+    pub duration: f64,
+    // 批量模拟时不计入统计的预热期
+    pub warmup: Warmup,
+    // 批量模拟时的电梯数量，不指定时用配置里的
+    pub lifts: Option<usize>,
+    // 批量模拟用的线程数，不指定时用所有的 CPU 核
+    pub threads: Option<usize>,
+    // 批量模拟时，在同样的客流下对比这几种调度策略，第一种是基准
+    pub compare: Vec<Dispatch>,
+    // 批量模拟后再按方向、电梯、楼层打印乘客的候梯、乘梯、全程时间
+    pub metrics: bool,
+    // 批量模拟后把图表保存到这个目录
+    pub charts: Option<String>,
+    pub chart_format: ChartFormat,
+    // 计算早高峰的往返时间和运送能力，和 --batch 一起使用时再用模拟对照
+    pub rtt: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            config: None,
+            profile: None,
+            intensity: None,
+            seed: None,
+            dispatch: Default::default(),
+            snapshot: SNAPSHOT_FILE.to_string(),
+            load: false,
+            trips: TRIPS_FILE.to_string(),
+            calls: CALLS_FILE.to_string(),
+            wear: WEAR_FILE.to_string(),
+            record: None,
+            replay: None,
+            batch: None,
+            duration: BATCH_DURATION_IN_SECONDS,
+            warmup: Warmup::Fixed(WARMUP_IN_SECONDS),
+            lifts: None,
+            threads: None,
+            compare: vec![],
+            metrics: false,
+            charts: None,
+            chart_format: Default::default(),
+            rtt: false,
+        }
+    }
+}
+
+pub fn usage() -> String {
+    let dispatches = Dispatch::ALL
+        .iter()
+        .map(|d| d.name())
+        .collect::<Vec<_>>()
+        .join("|");
+    format!("用法: elevator_scheduler [--config <配置文件>] [--profile <{}>] [--intensity <百分比>] [--seed <随机种子>] [--dispatch <{}>] [--snapshot <快照文件>] [--load <快照文件>] [--trips <行程文件>] [--calls <呼叫文件>] [--wear <磨损统计文件>] [--record <轨迹文件>] [--replay <轨迹文件>] [--batch <次数> [--duration <秒>] [--warmup <秒|auto>] [--lifts <电梯数量>] [--threads <线程数>] [--compare <策略>,<策略>...] [--metrics] [--charts <目录> [--chart-format <{}>]]] [--rtt]",
+            TrafficProfile::ALL
+                .iter()
+                .map(|p| p.name())
+                .collect::<Vec<_>>()
+                .join("|"),
+            dispatches,
+            ChartFormat::ALL
+                .iter()
+                .map(|f| f.name())
+                .collect::<Vec<_>>()
+                .join("|"))
+}
+
+// 解析命令行参数，args 不包含程序名
+pub fn parse_args<I: IntoIterator<Item=String>>(args: I) -> Result<Options, String> {
+    let mut opts = Options::default();
+    // 命令行指定的预热期，没指定时按模拟时长取默认值
+    let mut warmup = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args
+            .next()
+            .ok_or_else(|| format!("{} 后面缺少参数值", name));
+        match arg.as_str() {
+            "--config" => {
+                opts.config = Some(value("--config")?);
+            }
+            "--profile" => {
+                let name = value("--profile")?;
+                opts.profile = Some(TrafficProfile::from_name(&name)
+                    .ok_or_else(|| format!("未知的客流: {}", name))?);
+            }
+            "--intensity" => {
+                let v = value("--intensity")?;
+                opts.intensity = Some(v
+                    .parse()
+                    .ok()
+                    .filter(|i: &f64| i.is_finite() && *i > 0.0)
+                    .ok_or_else(|| format!("客流强度必须是正数: {}", v))?);
+            }
+            "--seed" => {
+                let v = value("--seed")?;
+                opts.seed = Some(v
+                    .parse()
+                    .map_err(|_| format!("随机种子必须是非负整数: {}", v))?);
+            }
+            "--dispatch" => {
+                let name = value("--dispatch")?;
+                opts.dispatch = Dispatch::from_name(&name)
+                    .ok_or_else(|| format!("未知的调度策略: {}", name))?;
+            }
+            "--compare" => {
+                opts.compare = value("--compare")?
+                    .split(',')
+                    .map(|name| Dispatch::from_name(name.trim())
+                        .ok_or_else(|| format!("未知的调度策略: {}", name)))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--snapshot" => {
+                opts.snapshot = value("--snapshot")?;
+            }
+            "--load" => {
+                opts.snapshot = value("--load")?;
+                opts.load = true;
+            }
+            "--trips" => {
+                opts.trips = value("--trips")?;
+            }
+            "--calls" => {
+                opts.calls = value("--calls")?;
+            }
+            "--wear" => {
+                opts.wear = value("--wear")?;
+            }
+            "--record" => {
+                opts.record = Some(value("--record")?);
+            }
+            "--replay" => {
+                opts.replay = Some(value("--replay")?);
+            }
+            "--batch" => {
+                let v = value("--batch")?;
+                opts.batch = Some(v
+                    .parse()
+                    .ok()
+                    .filter(|n: &usize| *n > 0)
+                    .ok_or_else(|| format!("模拟次数必须是正整数: {}", v))?);
+            }
+            "--duration" => {
+                let v = value("--duration")?;
+                opts.duration = v
+                    .parse()
+                    .ok()
+                    .filter(|d: &f64| d.is_finite() && *d > 0.0)
+                    .ok_or_else(|| format!("模拟时长必须是正数: {}", v))?;
+            }
+            "--warmup" => {
+                let v = value("--warmup")?;
+                warmup = Some(if v == "auto" {
+                    Warmup::Auto
+                } else {
+                    Warmup::Fixed(v
+                        .parse()
+                        .ok()
+                        .filter(|w: &f64| w.is_finite() && *w >= 0.0)
+                        .ok_or_else(|| format!("预热时长必须是非负数或者 auto: {}", v))?)
+                });
+            }
+            "--lifts" => {
+                let v = value("--lifts")?;
+                opts.lifts = Some(v
+                    .parse()
+                    .ok()
+                    .filter(|n: &usize| *n > 0)
+                    .ok_or_else(|| format!("电梯数量必须是正整数: {}", v))?);
+            }
+            "--threads" => {
+                let v = value("--threads")?;
+                opts.threads = Some(v
+                    .parse()
+                    .ok()
+                    .filter(|n: &usize| *n > 0)
+                    .ok_or_else(|| format!("线程数必须是正整数: {}", v))?);
+            }
+            "--metrics" => {
+                opts.metrics = true;
+            }
+            "--charts" => {
+                opts.charts = Some(value("--charts")?);
+            }
+            "--chart-format" => {
+                let name = value("--chart-format")?;
+                opts.chart_format = ChartFormat::from_name(&name)
+                    .ok_or_else(|| format!("未知的图表格式: {}", name))?;
+            }
+            "--rtt" => {
+                opts.rtt = true;
+            }
+            "-h" | "--help" => return Err(usage()),
+            _ => return Err(format!("未知的参数: {}", arg)),
+        }
+    }
+    if opts.load && opts.replay.is_some() {
+        return Err("--load 和 --replay 不能同时使用，轨迹文件里已经包含了开始时的状态".to_string());
+    }
+    if opts.replay.is_some() && opts.record.is_some() {
+        return Err("--replay 不打开窗口，不能和 --record 一起使用".to_string());
+    }
+    if opts.batch.is_some() && (opts.load || opts.record.is_some() || opts.replay.is_some()) {
+        return Err("--batch 不能和 --load、--record、--replay 一起使用".to_string());
+    }
+    opts.warmup = match warmup {
+        Some(Warmup::Fixed(warmup)) if warmup >= opts.duration => {
+            return Err(format!("预热时长{}秒必须比模拟时长{}秒短", warmup, opts.duration));
+        }
+        Some(warmup) => warmup,
+        // 默认的预热时长不超过模拟时长的一定比例，模拟时间短时也能用
+        None => Warmup::Fixed(WARMUP_IN_SECONDS.min(opts.duration * WARMUP_MAX_RATIO)),
+    };
+    if opts.metrics && opts.batch.is_none() {
+        return Err("--metrics 要和 --batch 一起使用".to_string());
+    }
+    if opts.charts.is_some() && opts.batch.is_none() {
+        return Err("--charts 要和 --batch 一起使用".to_string());
+    }
+    if opts.rtt && (!opts.compare.is_empty() || opts.metrics || opts.charts.is_some()) {
+        return Err("--rtt 不能和 --compare、--metrics、--charts 一起使用".to_string());
+    }
+    if !opts.compare.is_empty() {
+        if opts.batch.is_none() {
+            return Err("--compare 要和 --batch 一起使用".to_string());
+        }
+        if opts.metrics {
+            return Err("--metrics 不能和 --compare 一起使用".to_string());
+        }
+        if opts.charts.is_some() {
+            return Err("--charts 不能和 --compare 一起使用".to_string());
+        }
+        if opts.compare.len() < 2 {
+            return Err("--compare 至少要指定两种调度策略".to_string());
+        }
+    }
+    Ok(opts)
+}
@@ -7,12 +7,81 @@ pub const MAX_ELEVATOR_NUM: usize = 4;
 pub const MAX_FLOOR: TFloor = 40;
 // 最小电梯楼层数
 pub const MIN_FLOOR: TFloor = -4;
+// 默认不存在的楼层：地上 1 层下面就是地下 1 层，没有 0 层
+pub const SKIP_FLOORS: [TFloor; 1] = [0];
 // 最大承载人数
 pub const MAX_PERSON_CAPACITY: usize = 18;
 
-// 上下人等待时间, 单位：豪秒
-pub const SUSPEND_WAIT_IN_MILLISECONDS: u32 = 5 * 100;
-// 电梯每层的运行时间, 单位：豪秒
-pub const EVERY_FLOOR_RUN_TIME_IN_MILLISECONDS: u32 = 3000;
-// 电梯运行过程中的休眠时间, 单位：豪秒
+// 开门时间, 单位：豪秒
+pub const DOOR_OPENING_TIME_IN_MILLISECONDS: u32 = 1500;
+// 关门时间, 单位：豪秒
+pub const DOOR_CLOSING_TIME_IN_MILLISECONDS: u32 = 2000;
+// 没人进出时，门打开后的停留时间, 单位：豪秒
+pub const DOOR_DWELL_TIME_IN_MILLISECONDS: u32 = 2000;
+// 每进出一个人，增加的停留时间, 单位：豪秒
+pub const DOOR_DWELL_PER_PERSON_IN_MILLISECONDS: u32 = 800;
+// 一次停靠里最多重新开门的次数，超过后强制关门
+pub const DOOR_MAX_REOPEN_TIMES: u32 = 3;
+// 每层楼的高度, 单位：米
+pub const FLOOR_HEIGHT_IN_METERS: f32 = 3.0;
+// 电梯的额定速度, 单位：米/秒
+pub const RATED_SPEED_IN_METERS_PER_SECOND: f32 = 2.5;
+// 电梯的最大加速度, 单位：米/秒²
+pub const ACCELERATION_IN_METERS_PER_SECOND2: f32 = 1.0;
+// 电梯的最大加加速度, 单位：米/秒³
+pub const JERK_IN_METERS_PER_SECOND3: f32 = 1.6;
+// 电梯运行过程中刷新位置的时间间隔, 单位：豪秒
 pub const ELEVATOR_SLEEP_TIME_IN_MILLISECONDS: u32 = 1 * 100;
+// 兜底调度的时间间隔, 单位：秒
+pub const SCHEDULING_FALLBACK_IN_SECONDS: u64 = 30;
+// 大楼的总人数
+pub const BUILDING_POPULATION: u32 = 1200;
+// 大厅所在的楼层
+pub const LOBBY_FLOOR: TFloor = 1;
+// 默认的客流强度，每 5 分钟到达的人数占大楼总人数的百分比
+pub const TRAFFIC_INTENSITY_PERCENT: f64 = 12.0;
+// 电梯开门后，乘客走到轿厢门口的时间范围, 单位：秒
+pub const WALKING_TIME_IN_SECONDS: (f64, f64) = (1.0, 5.0);
+// 每人进入轿厢的时间, 单位：秒
+pub const BOARDING_TIME_IN_SECONDS: f64 = 1.2;
+// 每人走出轿厢的时间, 单位：秒
+pub const ALIGHTING_TIME_IN_SECONDS: f64 = 1.0;
+// 轿厢载荷率达到这个值时，候梯的人就不进去了
+pub const BALK_LOAD_FACTOR: f64 = 0.8;
+// 只走几层的乘客，等电梯超过这个时间就改走楼梯, 单位：秒
+pub const STAIRS_PATIENCE_IN_SECONDS: f64 = 60.0;
+// 最多走几层楼梯
+pub const STAIRS_MAX_FLOORS: u32 = 2;
+// 乘客体重的正态分布：平均值、标准差, 单位：千克
+pub const PASSENGER_WEIGHT_IN_KG: (f64, f64) = (70.0, 12.0);
+// 乘客体重的上下限（含随身物品）, 单位：千克
+pub const PASSENGER_WEIGHT_RANGE_IN_KG: (f64, f64) = (20.0, 150.0);
+// 电梯的额定载重, 单位：千克
+pub const RATED_LOAD_IN_KG: f64 = 1350.0;
+// 仪表盘的刷新间隔, 单位：秒
+pub const DASHBOARD_REFRESH_IN_SECONDS: u64 = 1;
+// 运行时检查配置文件有没有修改的时间间隔, 单位：秒
+pub const CONFIG_POLL_IN_SECONDS: u64 = 2;
+// 默认的快照文件
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+// 默认导出乘客行程、呼叫记录的文件，扩展名是 .jsonl 时写成 JSON Lines
+pub const TRIPS_FILE: &str = "trips.csv";
+pub const CALLS_FILE: &str = "calls.csv";
+// 默认导出每部电梯的使用和磨损统计的文件
+pub const WEAR_FILE: &str = "wear.csv";
+// 批量模拟后画的图的大小, 单位：像素
+pub const CHART_SIZE: (u32, u32) = (1024, 640);
+// 候梯时间分布图每一格的宽度, 单位：秒
+pub const WAITING_HISTOGRAM_BIN_IN_SECONDS: f64 = 5.0;
+// 批量模拟时每次模拟的默认时长, 单位：秒
+pub const BATCH_DURATION_IN_SECONDS: f64 = 3600.0;
+// 批量模拟时默认的预热时长，这段时间到达的乘客不计入统计, 单位：秒
+pub const WARMUP_IN_SECONDS: f64 = 300.0;
+// 没指定预热时长时，默认的预热时长最多占模拟时长的比例
+pub const WARMUP_MAX_RATIO: f64 = 0.25;
+// 自动检测稳态时，把候梯时间按这么长的时间段分段求平均, 单位：秒
+pub const STEADY_STATE_BUCKET_IN_SECONDS: f64 = 60.0;
+// 自动检测稳态时，移动平均向两边各取几个时间段
+pub const STEADY_STATE_WINDOW: usize = 5;
+// 自动检测稳态时，移动平均和稳态值相差不超过这个比例就算稳定
+pub const STEADY_STATE_TOLERANCE: f64 = 0.1;
@@ -0,0 +1,599 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use crate::building::{set_building, Building};
+use crate::conf::{BUILDING_POPULATION, ELEVATOR_SLEEP_TIME_IN_MILLISECONDS, FLOOR_HEIGHT_IN_METERS, LOBBY_FLOOR, MAX_ELEVATOR_NUM, MAX_FLOOR, MAX_PERSON_CAPACITY, MIN_FLOOR, RATED_LOAD_IN_KG, SCHEDULING_FALLBACK_IN_SECONDS, SKIP_FLOORS, TFloor, TRAFFIC_INTENSITY_PERCENT};
+use crate::door::DoorTimings;
+use crate::motion::MotionProfile;
+use crate::passenger::Behaviour;
+use crate::traffic::OdMatrix;
+
+// 运行时的配置，没写的项都取 conf.rs 里的默认值
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // 启动时的电梯数量
+    pub lifts: usize,
+    // 每部电梯的最大承载人数
+    pub capacity: usize,
+    // 每部电梯的额定载重, 单位：千克
+    pub rated_load: f64,
+    // 兜底调度的时间间隔, 单位：秒
+    pub scheduling_fallback: u64,
+    // 界面上刷新电梯位置的时间间隔, 单位：豪秒
+    pub refresh_interval: u32,
+    pub building: BuildingConfig,
+    pub door: DoorTimings,
+    pub motion: MotionProfile,
+    pub passengers: Behaviour,
+    pub traffic: TrafficConfig,
+    pub dispatch: DispatchConfig,
+    // 按电梯序号单独设置的参数，没有列出的电梯用上面统一的参数
+    pub lift_specs: Vec<LiftSpec>,
+}
+
+// 一部电梯单独的参数，没写的项用统一的参数
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiftSpec {
+    // 最大承载人数
+    pub capacity: Option<usize>,
+    // 额定载重, 单位：千克
+    pub rated_load: Option<f64>,
+    // 额定速度, 单位：米/秒
+    pub rated_speed: Option<f32>,
+    // 开关门的各项时间，要写的话没写的项取内置的默认值
+    pub door: Option<DoorTimings>,
+    // 停靠的楼层，不写时每层都停
+    pub floors: Option<Vec<TFloor>>,
+    // 分区：只应答这些楼层的呼梯，不写时应答所有停靠的楼层
+    pub zone: Option<Vec<TFloor>>,
+    // 闲置时回到这一层待命，不写时停在原地
+    pub parking: Option<TFloor>,
+}
+
+// 大楼的楼层
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildingConfig {
+    // 最低楼层
+    pub lowest: TFloor,
+    // 最高楼层
+    pub highest: TFloor,
+    // 每层楼的高度, 单位：米
+    pub floor_height: f32,
+    // 大厅所在的楼层
+    pub lobby: TFloor,
+    // 不存在的楼层，默认没有 0 层，还可以去掉不吉利的 4、13、14 层
+    pub skip: Vec<TFloor>,
+    // 楼层显示的名称，键是楼层号，比如 "-1" = "B1"、"1" = "G"； 没写的楼层显示楼层号
+    pub labels: BTreeMap<String, String>,
+}
+
+// 自动客流
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrafficConfig {
+    // 大楼的总人数
+    pub population: u32,
+    // 默认的客流强度，每 5 分钟到达的人数占大楼总人数的百分比
+    pub intensity: f64,
+    // 到达率随时间的变化，(开始时刻, 倍数)，时刻的单位：秒，从开启客流时算起
+    // 每段的到达率是客流强度对应的到达率乘以倍数，第一段从 0 开始，不写时到达率不变
+    pub rates: Vec<(f64, f64)>,
+    // rates 循环的周期, 单位：秒； 不写时不循环，最后一段一直持续下去
+    pub period: Option<f64>,
+    // 自定义客流的 OD 矩阵，od[i][j] 是从第 i 层去第 j 层的相对权重，楼层从低到高排列，不包括 building.skip 的楼层
+    // 不写时自定义客流是层间均匀出行
+    pub od: Vec<Vec<f64>>,
+}
+
+// 调度的参数
+// 最短到达时间策略里，把呼叫派给一部电梯的代价 = 估算的到达时间 + load_weight × 载荷率 + stop_weight × 已有的停靠楼层数
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DispatchConfig {
+    // 载荷率的权重, 单位：秒，越大越少往挤的电梯派
+    pub load_weight: f64,
+    // 已有停靠楼层数的权重, 单位：秒，越大越少往要停很多层的电梯派
+    pub stop_weight: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lifts: MAX_ELEVATOR_NUM,
+            capacity: MAX_PERSON_CAPACITY,
+            rated_load: RATED_LOAD_IN_KG,
+            scheduling_fallback: SCHEDULING_FALLBACK_IN_SECONDS,
+            refresh_interval: ELEVATOR_SLEEP_TIME_IN_MILLISECONDS,
+            building: Default::default(),
+            door: Default::default(),
+            motion: Default::default(),
+            passengers: Default::default(),
+            traffic: Default::default(),
+            dispatch: Default::default(),
+            lift_specs: vec![],
+        }
+    }
+}
+
+impl Default for BuildingConfig {
+    fn default() -> Self {
+        Self {
+            lowest: MIN_FLOOR,
+            highest: MAX_FLOOR,
+            floor_height: FLOOR_HEIGHT_IN_METERS,
+            lobby: LOBBY_FLOOR,
+            skip: SKIP_FLOORS.to_vec(),
+            labels: Default::default(),
+        }
+    }
+}
+
+impl Default for TrafficConfig {
+    fn default() -> Self {
+        Self {
+            population: BUILDING_POPULATION,
+            intensity: TRAFFIC_INTENSITY_PERCENT,
+            rates: vec![],
+            period: None,
+            od: vec![],
+        }
+    }
+}
+
+// 校验时最多列出几对不能直达的楼层
+const MAX_UNSERVED_SHOWN: usize = 10;
+
+// 收集校验时发现的问题，每条都带上配置项的完整路径
+#[derive(Default)]
+struct Errors(Vec<String>);
+
+impl Errors {
+    fn check(&mut self, ok: bool, path: &str, rule: &str, value: impl std::fmt::Display) {
+        if !ok {
+            self.0.push(format!("{} {}，现在是 {}", path, rule, value));
+        }
+    }
+
+    fn positive<T: PartialOrd + Default + std::fmt::Display>(&mut self, path: &str, value: T) {
+        self.check(value > T::default(), path, "必须大于 0", value);
+    }
+
+    fn non_negative<T: PartialOrd + Default + std::fmt::Display>(&mut self, path: &str, value: T) {
+        self.check(value >= T::default(), path, "不能小于 0", value);
+    }
+}
+
+impl Config {
+    // 读取配置文件，扩展名是 .json 的按 JSON 解析，其它的按 TOML 解析
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("读取配置文件{}失败: {}", path.display(), e))?;
+        let config: Self = if path.extension().map_or(false, |ext| ext == "json") {
+            serde_json::from_str(&content).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&content).map_err(|e| e.to_string())
+        }.map_err(|e| format!("配置文件{}格式错误: {}", path.display(), e))?;
+        config
+            .validate()
+            .map_err(|e| format!("配置文件{}有误:\n  {}", path.display(), e.join("\n  ")))?;
+        Ok(config)
+    }
+
+    // 检查各项取值，返回所有不合法的项
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Errors::default();
+        errors.positive("lifts", self.lifts);
+        errors.positive("capacity", self.capacity);
+        errors.positive("rated_load", self.rated_load);
+        errors.positive("scheduling_fallback", self.scheduling_fallback);
+        errors.positive("refresh_interval", self.refresh_interval);
+
+        let b = &self.building;
+        errors.positive("building.floor_height", b.floor_height);
+        if b.highest <= b.lowest {
+            errors.check(false, "building.highest", &format!("必须大于 building.lowest({})", b.lowest), b.highest);
+        } else {
+            match self.to_building() {
+                Err(e) => errors.0.push(e),
+                Ok(building) => {
+                    errors.check(building.index(b.lobby).is_some(), "building.lobby",
+                                 &format!("必须是 {}~{} 之间除 building.skip 以外的楼层", b.lowest, b.highest), b.lobby);
+                    // 早晚高峰的客流都在大厅和大厅以上的楼层之间
+                    errors.check(b.lobby < building.highest(), "building.lobby",
+                                 &format!("上面至少要有一层，必须低于 building.highest({})", building.highest()), b.lobby);
+                    let mut names = BTreeMap::new();
+                    for floor in building.floors() {
+                        if floor.name.trim().is_empty() {
+                            errors.0.push(format!("building.labels 里{}层的名称是空的", floor.number));
+                        } else if let Some(other) = names.insert(floor.name.clone(), floor.number) {
+                            errors.0.push(format!("building.labels 里{}层和{}层的名称都是 {}", other, floor.number, floor.name));
+                        }
+                    }
+                }
+            }
+        }
+
+        let d = &self.door;
+        errors.positive("door.opening", d.opening);
+        errors.positive("door.closing", d.closing);
+
+        let m = &self.motion;
+        errors.positive("motion.rated_speed", m.rated_speed);
+        errors.positive("motion.acceleration", m.acceleration);
+        errors.positive("motion.jerk", m.jerk);
+
+        let p = &self.passengers;
+        errors.non_negative("passengers.walking[0]", p.walking.0);
+        errors.check(p.walking.1 >= p.walking.0, "passengers.walking[1]",
+                     &format!("不能小于 passengers.walking[0]({})", p.walking.0), p.walking.1);
+        errors.positive("passengers.weight.min", p.weight.min);
+        errors.non_negative("passengers.weight.std_dev", p.weight.std_dev);
+        errors.check(p.weight.max >= p.weight.min, "passengers.weight.max",
+                     &format!("不能小于 passengers.weight.min({})", p.weight.min), p.weight.max);
+        errors.check(p.weight.mean >= p.weight.min && p.weight.mean <= p.weight.max, "passengers.weight.mean",
+                     &format!("必须在 {}~{} 之间", p.weight.min, p.weight.max), p.weight.mean);
+        errors.non_negative("passengers.boarding", p.boarding);
+        errors.non_negative("passengers.alighting", p.alighting);
+        errors.check(p.balk_load_factor > 0.0 && p.balk_load_factor <= 1.0,
+                     "passengers.balk_load_factor", "必须在 0~1 之间且大于 0", p.balk_load_factor);
+        if let Some(patience) = p.stairs_patience {
+            errors.positive("passengers.stairs_patience", patience);
+        }
+
+        if let Ok(building) = self.to_building() {
+            for (i, spec) in self.lift_specs.iter().enumerate() {
+                let path = |name: &str| format!("lift_specs[{}].{}", i, name);
+                if let Some(capacity) = spec.capacity {
+                    errors.positive(&path("capacity"), capacity);
+                }
+                if let Some(rated_load) = spec.rated_load {
+                    errors.positive(&path("rated_load"), rated_load);
+                }
+                if let Some(rated_speed) = spec.rated_speed {
+                    errors.positive(&path("rated_speed"), rated_speed);
+                }
+                if let Some(door) = &spec.door {
+                    errors.positive(&path("door.opening"), door.opening);
+                    errors.positive(&path("door.closing"), door.closing);
+                }
+                if let Some(floors) = &spec.floors {
+                    if floors.is_empty() {
+                        errors.0.push(format!("{} 至少要停靠一层", path("floors")));
+                    }
+                    for floor in floors.iter().filter(|f| building.index(**f).is_none()) {
+                        errors.check(false, &path("floors"), &format!("只能是 {}~{} 之间除 building.skip 以外的楼层", b.lowest, b.highest), floor);
+                    }
+                }
+                let stops = |floor: &TFloor| building.index(*floor).is_some()
+                    && spec.floors.as_ref().map_or(true, |floors| floors.contains(floor));
+                if let Some(zone) = &spec.zone {
+                    if zone.is_empty() {
+                        errors.0.push(format!("{} 至少要有一层", path("zone")));
+                    }
+                    for floor in zone.iter().filter(|f| !stops(f)) {
+                        errors.check(false, &path("zone"), "只能是这部电梯停靠的楼层", floor);
+                    }
+                }
+                if let Some(parking) = spec.parking {
+                    errors.check(stops(&parking), &path("parking"), "必须是这部电梯停靠的楼层", parking);
+                }
+            }
+            // 不换乘，启动时的电梯里任意两层之间都要有一部电梯应答出发楼层的呼梯、同时停靠目的楼层，乘客才能从一层到另一层
+            let specs = (0..self.lifts)
+                .map(|no| self.lift_specs.get(no).cloned().unwrap_or_default())
+                .collect::<Vec<_>>();
+            if !specs.iter().any(|spec| spec.floors.is_none() && spec.zone.is_none()) {
+                let floors = building.numbers();
+                let reaches = |spec: &LiftSpec, a: &TFloor, b: &TFloor| {
+                    spec.floors.as_ref().map_or(true, |f| f.contains(a) && f.contains(b))
+                        && spec.zone.as_ref().map_or(true, |zone| zone.contains(a))
+                };
+                let unserved = floors
+                    .iter()
+                    .flat_map(|a| floors.iter().filter(move |b| *b != a).map(move |b| (*a, *b)))
+                    .filter(|(a, b)| !specs.iter().any(|spec| reaches(spec, a, b)))
+                    .collect::<Vec<_>>();
+                if !unserved.is_empty() {
+                    let shown = unserved
+                        .iter()
+                        .take(MAX_UNSERVED_SHOWN)
+                        .map(|(a, b)| format!("{}到{}", a, b))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    errors.0.push(format!("lift_specs 里前{}部电梯没有一部能应答出发楼层的呼梯、同时停靠目的楼层，乘客不能直达: {}{}",
+                                          self.lifts, shown,
+                                          if unserved.len() > MAX_UNSERVED_SHOWN { format!(" 等{}对", unserved.len()) } else { String::new() }));
+                }
+            }
+        }
+
+        errors.positive("traffic.population", self.traffic.population);
+        let t = &self.traffic;
+        errors.check(t.intensity.is_finite() && t.intensity > 0.0,
+                     "traffic.intensity", "必须大于 0", t.intensity);
+        for (i, (start, factor)) in t.rates.iter().enumerate() {
+            let path = format!("traffic.rates[{}]", i);
+            match i {
+                0 => errors.check(*start == 0.0, &path, "的开始时刻必须是 0", start),
+                _ => errors.check(start.is_finite() && *start > t.rates[i - 1].0, &path,
+                                  &format!("的开始时刻必须大于前一段的开始时刻({})", t.rates[i - 1].0), start),
+            }
+            errors.check(factor.is_finite() && *factor >= 0.0, &path, "的倍数不能小于 0", factor);
+        }
+        if !t.rates.is_empty() && t.rates.iter().all(|(_, factor)| *factor <= 0.0) {
+            errors.0.push("traffic.rates 至少要有一段的倍数大于 0".to_string());
+        }
+        if let Some(period) = t.period {
+            let last = t.rates.last().map_or(0.0, |(start, _)| *start);
+            errors.check(period.is_finite() && period > last, "traffic.period",
+                         &format!("必须大于 traffic.rates 最后一段的开始时刻({})", last), period);
+        }
+        if !t.od.is_empty() {
+            if let Ok(building) = self.to_building() {
+                if let Err(e) = OdMatrix::new(building.numbers(), t.od.clone()) {
+                    errors.0.push(format!("traffic.od {}", e));
+                }
+            }
+        }
+
+        errors.non_negative("dispatch.load_weight", self.dispatch.load_weight);
+        errors.non_negative("dispatch.stop_weight", self.dispatch.stop_weight);
+
+        if errors.0.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.0)
+        }
+    }
+
+    // 运行时换成 new 配置前的检查，返回所有不能在运行时修改的项
+    // 楼层和电梯数量变了的话，现在电梯的状态就对不上了，要重启程序才能生效
+    pub fn check_reload(&self, new: &Config) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        let mut unchanged = |path: &str, old: String, new: String| if old != new {
+            errors.push(format!("{} 不能在运行时修改({} -> {})，要重启程序才能生效", path, old, new));
+        };
+        unchanged("lifts", self.lifts.to_string(), new.lifts.to_string());
+        let (a, b) = (&self.building, &new.building);
+        unchanged("building.lowest", a.lowest.to_string(), b.lowest.to_string());
+        unchanged("building.highest", a.highest.to_string(), b.highest.to_string());
+        unchanged("building.floor_height", a.floor_height.to_string(), b.floor_height.to_string());
+        unchanged("building.skip", format!("{:?}", a.skip), format!("{:?}", b.skip));
+        for no in 0..self.lift_specs.len().max(new.lift_specs.len()) {
+            let floors = |config: &Config| config.lift_specs
+                .get(no)
+                .and_then(|spec| spec.floors.as_ref())
+                .map(|floors| format!("{:?}", floors))
+                .unwrap_or_else(|| "所有楼层".to_string());
+            unchanged(&format!("lift_specs[{}].floors", no), floors(self), floors(new));
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // no 号电梯应答不应答 floor 层的呼梯，没设置分区的电梯应答所有停靠的楼层
+    pub fn in_zone(&self, no: usize, floor: TFloor) -> bool {
+        self.lift_specs
+            .get(no)
+            .and_then(|spec| spec.zone.as_ref())
+            .map_or(true, |zone| zone.contains(&floor))
+    }
+
+    // 按配置生成大楼
+    pub fn to_building(&self) -> Result<Building, String> {
+        let b = &self.building;
+        let mut building = Building::uniform_skipping(b.lowest, b.highest, &b.skip, b.floor_height)
+            .map_err(|e| format!("building {}", e))?;
+        for (floor, name) in &b.labels {
+            let number = floor
+                .trim()
+                .parse()
+                .map_err(|_| format!("building.labels 里的 \"{}\" 不是楼层号", floor))?;
+            building
+                .set_name(number, name.clone())
+                .map_err(|e| format!("building.labels 里的 \"{}\": {}", floor, e))?;
+        }
+        Ok(building)
+    }
+}
+
+lazy_static! {
+    // 当前的配置
+    static ref CONFIG: RwLock<Arc<Config>> = RwLock::new(Arc::new(Config::default()));
+}
+
+// 当前的配置
+pub fn config() -> Arc<Config> {
+    CONFIG.read().unwrap().clone()
+}
+
+// 替换当前的配置，同时按配置替换大楼，要在创建电梯之前调用
+pub fn set_config(config: Config) {
+    set_building(config.to_building().unwrap());
+    *CONFIG.write().unwrap() = Arc::new(config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 校验失败时返回所有的错误
+    fn errors(config: &Config) -> Vec<String> {
+        config.validate().unwrap_err()
+    }
+
+    fn assert_error(config: &Config, expected: &str) {
+        let errors = errors(config);
+        assert!(errors.iter().any(|e| e == expected), "{:?} 里没有 {}", errors, expected);
+    }
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn positive_values() {
+        let mut config = Config::default();
+        config.capacity = 0;
+        assert_eq!(errors(&config), vec!["capacity 必须大于 0，现在是 0".to_string()]);
+    }
+
+    #[test]
+    fn highest_above_lowest() {
+        let mut config = Config::default();
+        config.building.highest = config.building.lowest;
+        assert_error(&config, "building.highest 必须大于 building.lowest(-4)，现在是 -4");
+    }
+
+    #[test]
+    fn lobby_below_highest() {
+        let mut config = Config::default();
+        config.building.lobby = 40;
+        assert_eq!(errors(&config), vec!["building.lobby 上面至少要有一层，必须低于 building.highest(40)，现在是 40".to_string()]);
+    }
+
+    #[test]
+    fn lobby_not_skipped() {
+        let mut config = Config::default();
+        config.building.skip = vec![0, 1];
+        assert_error(&config, "building.lobby 必须是 -4~40 之间除 building.skip 以外的楼层，现在是 1");
+    }
+
+    #[test]
+    fn unique_labels() {
+        let mut config = Config::default();
+        config.building.labels.insert("2".to_string(), "3".to_string());
+        assert_eq!(errors(&config), vec!["building.labels 里2层和3层的名称都是 3".to_string()]);
+    }
+
+    #[test]
+    fn passenger_ranges() {
+        let mut config = Config::default();
+        config.passengers.walking = (5.0, 2.0);
+        config.passengers.weight.mean = config.passengers.weight.max + 1.0;
+        config.passengers.balk_load_factor = 1.5;
+        let errors = errors(&config);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(errors[0], "passengers.walking[1] 不能小于 passengers.walking[0](5)，现在是 2");
+        assert!(errors[1].starts_with("passengers.weight.mean 必须在 "), "{}", errors[1]);
+        assert_eq!(errors[2], "passengers.balk_load_factor 必须在 0~1 之间且大于 0，现在是 1.5");
+    }
+
+    #[test]
+    fn lift_spec_floors() {
+        let mut config = Config::default();
+        config.lifts = 1;
+        config.lift_specs = vec![LiftSpec {
+            floors: Some(vec![1, 2, 99]),
+            ..Default::default()
+        }];
+        let errors = errors(&config);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(errors[0], "lift_specs[0].floors 只能是 -4~40 之间除 building.skip 以外的楼层，现在是 99");
+        assert!(errors[1].starts_with("lift_specs 里前1部电梯没有一部能应答出发楼层的呼梯、同时停靠目的楼层，乘客不能直达: -4到-3,-4到-2,"), "{}", errors[1]);
+    }
+
+    #[test]
+    fn every_trip_has_a_lift() {
+        // 两部电梯合起来停靠了所有的楼层，但是从地下室不能直达 2 层以上
+        let mut config = Config::default();
+        config.lifts = 2;
+        config.lift_specs = vec![
+            LiftSpec {
+                floors: Some(vec![-4, -3, -2, -1, 1]),
+                ..Default::default()
+            },
+            LiftSpec {
+                floors: Some((1..=40).collect()),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(errors(&config), vec!["lift_specs 里前2部电梯没有一部能应答出发楼层的呼梯、同时停靠目的楼层，乘客不能直达: -4到2,-4到3,-4到4,-4到5,-4到6,-4到7,-4到8,-4到9,-4到10,-4到11 等312对".to_string()]);
+        // 大厅换乘不算，要有一部电梯从地下室直达
+        config.lift_specs[0].floors = Some((-4..=40).filter(|f| *f != 0).collect());
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn traffic_rates() {
+        let mut config = Config::default();
+        config.traffic.rates = vec![(10.0, 1.0), (5.0, -1.0)];
+        config.traffic.period = Some(3.0);
+        assert_eq!(errors(&config), vec![
+            "traffic.rates[0] 的开始时刻必须是 0，现在是 10".to_string(),
+            "traffic.rates[1] 的开始时刻必须大于前一段的开始时刻(10)，现在是 5".to_string(),
+            "traffic.rates[1] 的倍数不能小于 0，现在是 -1".to_string(),
+            "traffic.period 必须大于 traffic.rates 最后一段的开始时刻(5)，现在是 3".to_string(),
+        ]);
+        config.traffic.rates = vec![(0.0, 2.0), (600.0, 0.5)];
+        config.traffic.period = Some(1800.0);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn traffic_od() {
+        let mut config = Config::default();
+        config.traffic.od = vec![vec![1.0; 3]; 3];
+        assert_eq!(errors(&config), vec!["traffic.od OD 矩阵必须是 44x44 的方阵".to_string()]);
+        config.traffic.od = vec![vec![1.0; 44]; 44];
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn zones_and_parking() {
+        let mut config = Config::default();
+        config.lifts = 2;
+        config.lift_specs = vec![
+            LiftSpec {
+                floors: Some(vec![-1, 1, 2, 3]),
+                zone: Some(vec![1, 5]),
+                parking: Some(4),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(errors(&config), vec![
+            "lift_specs[0].zone 只能是这部电梯停靠的楼层，现在是 5".to_string(),
+            "lift_specs[0].parking 必须是这部电梯停靠的楼层，现在是 4".to_string(),
+        ]);
+        // 两部电梯都分区的话，分区合起来要应答所有的楼层
+        config.lift_specs = vec![
+            LiftSpec {
+                zone: Some((-4..=20).filter(|f| *f != 0).collect()),
+                parking: Some(1),
+                ..Default::default()
+            },
+            LiftSpec {
+                zone: Some((21..=39).collect()),
+                parking: Some(30),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(errors(&config), vec!["lift_specs 里前2部电梯没有一部能应答出发楼层的呼梯、同时停靠目的楼层，乘客不能直达: 40到-4,40到-3,40到-2,40到-1,40到1,40到2,40到3,40到4,40到5,40到6 等43对".to_string()]);
+        config.lift_specs[1].zone = Some((21..=40).collect());
+        assert_eq!(config.validate(), Ok(()));
+        assert!(config.in_zone(0, 20) && !config.in_zone(0, 21));
+        assert!(config.in_zone(1, 40) && config.in_zone(2, 40));
+    }
+
+    #[test]
+    fn collects_all_errors() {
+        let mut config = Config::default();
+        config.capacity = 0;
+        config.motion.jerk = 0.0;
+        config.traffic.population = 0;
+        assert_eq!(errors(&config), vec![
+            "capacity 必须大于 0，现在是 0".to_string(),
+            "motion.jerk 必须大于 0，现在是 0".to_string(),
+            "traffic.population 必须大于 0，现在是 0".to_string(),
+        ]);
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+
+// 把呼叫派给哪部电梯的策略
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum Dispatch {
+    // 最近的同向电梯：只考虑静止的、或者同方向还没走过这层的电梯，上下两边各取最近的一部，再按到达时间选
+    NearestCar,
+    // 最短到达时间：所有能用的电梯都算一遍，反方向的电梯算上掉头的时间，选最早到的
+    LeastEta,
+}
+
+impl Default for Dispatch {
+    fn default() -> Self {
+        Dispatch::NearestCar
+    }
+}
+
+impl Display for Dispatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use Dispatch::*;
+        write!(f, "{}", match self {
+            NearestCar => "最近同向",
+            LeastEta => "最短到达时间",
+        })
+    }
+}
+
+impl Dispatch {
+    pub const ALL: [Dispatch; 2] = [
+        Dispatch::NearestCar,
+        Dispatch::LeastEta,
+    ];
+
+    // 命令行里使用的名称
+    pub fn name(&self) -> &'static str {
+        use Dispatch::*;
+        match self {
+            NearestCar => "nearest-car",
+            LeastEta => "least-eta",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|d| d.name() == name)
+            .copied()
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::conf::{DOOR_CLOSING_TIME_IN_MILLISECONDS, DOOR_DWELL_PER_PERSON_IN_MILLISECONDS, DOOR_DWELL_TIME_IN_MILLISECONDS, DOOR_MAX_REOPEN_TIMES, DOOR_OPENING_TIME_IN_MILLISECONDS};
+
+// 电梯门的状态
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DoorState {
+    // 门已关好，电梯可以运行
+    Closed,
+    // 正在开门
+    Opening,
+    // 门已打开，正在等人进出
+    Open,
+    // 正在关门
+    Closing,
+    // 关门过程中又重新开门
+    Reopening,
+}
+
+impl Default for DoorState {
+    fn default() -> Self {
+        DoorState::Closed
+    }
+}
+
+impl Display for DoorState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use DoorState::*;
+        write!(f, "{}", match self {
+            Closed => "已关门",
+            Opening => "开门中",
+            Open => "已开门",
+            Closing => "关门中",
+            Reopening => "重新开门",
+        })
+    }
+}
+
+// 开关门的各项时间, 单位：豪秒
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DoorTimings {
+    // 开门需要的时间
+    pub opening: u32,
+    // 关门需要的时间
+    pub closing: u32,
+    // 没人进出时，门打开后的停留时间
+    pub dwell: u32,
+    // 调度估算到站时间时，途中停靠的楼层每有一个人下电梯增加的停留时间
+    // 实际的停留时间按乘客进出轿厢的时间算
+    pub dwell_per_person: u32,
+    // 重新开门超过这个次数后，就强制慢速关门
+    pub max_reopen: u32,
+}
+
+impl Default for DoorTimings {
+    fn default() -> Self {
+        Self {
+            opening: DOOR_OPENING_TIME_IN_MILLISECONDS,
+            closing: DOOR_CLOSING_TIME_IN_MILLISECONDS,
+            dwell: DOOR_DWELL_TIME_IN_MILLISECONDS,
+            dwell_per_person: DOOR_DWELL_PER_PERSON_IN_MILLISECONDS,
+            max_reopen: DOOR_MAX_REOPEN_TIMES,
+        }
+    }
+}
+
+impl DoorTimings {
+    // 有 persons 个人进出时，门打开后的停留时间
+    pub fn dwell_for(&self, persons: u32) -> u32 {
+        self.dwell + self.dwell_per_person * persons
+    }
+
+    // 一次完整的开关门需要的时间
+    pub fn cycle(&self, persons: u32) -> u32 {
+        self.opening + self.dwell_for(persons) + self.closing
+    }
+
+    // 强制关门时的关门时间，比平时慢一半
+    fn nudging_closing(&self) -> u32 {
+        self.closing * 3 / 2
+    }
+}
+
+// 电梯门
+// 每次状态变化都会得到一个新的序号和持续时间，持续时间到了之后，用同一个序号调用 on_timer 进入下一个状态
+// 序号对不上的定时器是过期的，直接忽略
+#[derive(Clone, Debug, Default)]
+pub struct Door {
+    pub state: DoorState,
+    pub timings: DoorTimings,
+    // 当前状态的序号
+    pub seq: u32,
+    // 本次停靠，门打开后的停留时间
+    pub dwell: u32,
+    // 本次停靠重新开门的次数
+    pub reopen_times: u32,
+    // 正在强制关门，不再响应开门
+    pub nudging: bool,
+    // 当前状态开始的时刻，以及持续的时间
+    since: Option<Instant>,
+    duration: u32,
+}
+
+impl Door {
+    pub fn is_closed(&self) -> bool {
+        self.state == DoorState::Closed
+    }
+
+    // 电梯到站开门，exchange 是门开好后乘客进出需要的时间, 单位：豪秒
+    // 返回开门需要的时间
+    pub fn open(&mut self, exchange: u32, now: Instant) -> Option<u32> {
+        match self.state {
+            DoorState::Closed => {
+                self.dwell = self.timings.dwell + exchange;
+                self.reopen_times = 0;
+                self.nudging = false;
+                Some(self.enter(DoorState::Opening, self.timings.opening, now))
+            }
+            // 门已经开着，延长停留时间
+            _ => self.press_open(now),
+        }
+    }
+
+    // 定时器到期，进入下一个状态
+    // 返回下一个状态的持续时间，门关好后返回 None
+    pub fn on_timer(&mut self, seq: u32, now: Instant) -> Option<u32> {
+        if seq != self.seq {
+            return None;
+        }
+        match self.state {
+            DoorState::Opening | DoorState::Reopening => Some(self.enter(DoorState::Open, self.dwell, now)),
+            DoorState::Open => {
+                let closing = if self.nudging { self.timings.nudging_closing() } else { self.timings.closing };
+                Some(self.enter(DoorState::Closing, closing, now))
+            }
+            DoorState::Closing => {
+                self.enter(DoorState::Closed, 0, now);
+                None
+            }
+            DoorState::Closed => None,
+        }
+    }
+
+    // 按开门按钮
+    pub fn press_open(&mut self, now: Instant) -> Option<u32> {
+        match self.state {
+            // 门开着，重新开始计算停留时间
+            DoorState::Open => Some(self.enter(DoorState::Open, self.dwell, now)),
+            DoorState::Closing if !self.nudging => {
+                self.reopen_times += 1;
+                if self.reopen_times >= self.timings.max_reopen {
+                    // 反复重新开门，下次关门就强制关门
+                    self.nudging = true;
+                }
+                // 门关到哪里，就从哪里重新打开
+                let closed = self.elapsed(now).min(self.duration);
+                let reopening = self.timings.opening * closed / self.duration.max(1);
+                Some(self.enter(DoorState::Reopening, reopening, now))
+            }
+            _ => None,
+        }
+    }
+
+    // 门开着时保持开门 millis 豪秒，比如超载报警时
+    pub fn hold(&mut self, millis: u32, now: Instant) -> Option<u32> {
+        match self.state {
+            DoorState::Open => Some(self.enter(DoorState::Open, millis, now)),
+            _ => None,
+        }
+    }
+
+    // 按关门按钮，门开着时马上关门
+    pub fn press_close(&mut self, now: Instant) -> Option<u32> {
+        match self.state {
+            DoorState::Open => Some(self.enter(DoorState::Closing, self.timings.closing, now)),
+            _ => None,
+        }
+    }
+
+    // 到门关好还需要的时间, 单位：豪秒
+    pub fn remaining(&self, now: Instant) -> u32 {
+        let left = self.duration.saturating_sub(self.elapsed(now));
+        match self.state {
+            DoorState::Closed => 0,
+            DoorState::Opening | DoorState::Reopening => left + self.dwell + self.timings.closing,
+            DoorState::Open => left + self.timings.closing,
+            DoorState::Closing => left,
+        }
+    }
+
+    // 当前状态已经持续的时间，以及应该持续的时间, 单位：豪秒
+    pub fn progress(&self, now: Instant) -> (u32, u32) {
+        (self.elapsed(now).min(self.duration), self.duration)
+    }
+
+    // 从快照恢复：当前状态应该持续 duration 豪秒，已经持续了 elapsed 豪秒
+    // 返回剩下的时间
+    pub fn resume(&mut self, elapsed: u32, duration: u32, now: Instant) -> u32 {
+        self.since = Some(now
+            .checked_sub(Duration::from_millis(elapsed as u64))
+            .unwrap_or(now));
+        self.duration = duration;
+        duration.saturating_sub(elapsed)
+    }
+
+    fn elapsed(&self, now: Instant) -> u32 {
+        self.since
+            .and_then(|since| now.checked_duration_since(since))
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u32
+    }
+
+    fn enter(&mut self, state: DoorState, duration: u32, now: Instant) -> u32 {
+        self.state = state;
+        self.seq = self.seq.wrapping_add(1);
+        self.since = Some(now);
+        self.duration = duration;
+        duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // 开门 1000、关门 2000、停留 3000 豪秒，重新开门 2 次后强制关门
+    fn door() -> Door {
+        Door {
+            timings: DoorTimings {
+                opening: 1000,
+                closing: 2000,
+                dwell: 3000,
+                dwell_per_person: 500,
+                max_reopen: 2,
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn full_cycle() {
+        let t0 = Instant::now();
+        let mut door = door();
+        assert_eq!(door.open(1200, t0), Some(1000));
+        assert_eq!(door.state, DoorState::Opening);
+        assert_eq!(door.remaining(t0), 1000 + 4200 + 2000);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(1000)), Some(4200));
+        assert_eq!(door.state, DoorState::Open);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(5200)), Some(2000));
+        assert_eq!(door.state, DoorState::Closing);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(7200)), None);
+        assert!(door.is_closed());
+        assert_eq!(door.remaining(t0 + ms(7200)), 0);
+    }
+
+    #[test]
+    fn stale_timers_are_ignored() {
+        let t0 = Instant::now();
+        let mut door = door();
+        door.open(0, t0);
+        let opening = door.seq;
+        door.on_timer(opening, t0 + ms(1000));
+        // 门开着时按开门按钮，停留时间重新计算，之前的定时器作废
+        let open = door.seq;
+        assert_eq!(door.press_open(t0 + ms(2000)), Some(3000));
+        assert_eq!(door.on_timer(open, t0 + ms(4000)), None);
+        assert_eq!(door.on_timer(opening, t0 + ms(4000)), None);
+        assert_eq!(door.state, DoorState::Open);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(5000)), Some(2000));
+    }
+
+    #[test]
+    fn reopen_from_where_the_door_stopped() {
+        let t0 = Instant::now();
+        let mut door = door();
+        door.open(0, t0);
+        door.on_timer(door.seq, t0);
+        door.press_close(t0);
+        // 关了一半时重新开门，开门也只要一半的时间
+        assert_eq!(door.press_open(t0 + ms(1000)), Some(500));
+        assert_eq!(door.state, DoorState::Reopening);
+        assert_eq!(door.reopen_times, 1);
+        assert!(!door.nudging);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(1500)), Some(3000));
+        assert_eq!(door.state, DoorState::Open);
+    }
+
+    #[test]
+    fn nudging_after_max_reopen() {
+        let t0 = Instant::now();
+        let mut door = door();
+        door.open(0, t0);
+        door.on_timer(door.seq, t0);
+        for _ in 0..2 {
+            door.press_close(t0);
+            door.press_open(t0);
+            door.on_timer(door.seq, t0);
+        }
+        assert!(door.nudging);
+        // 强制关门比平时慢一半，关门时也不再响应开门
+        assert_eq!(door.on_timer(door.seq, t0), Some(3000));
+        assert_eq!(door.state, DoorState::Closing);
+        assert_eq!(door.press_open(t0), None);
+        assert_eq!(door.on_timer(door.seq, t0 + ms(3000)), None);
+        assert!(door.is_closed());
+        // 下次到站开门重新计数
+        door.open(0, t0 + ms(4000));
+        assert_eq!(door.reopen_times, 0);
+        assert!(!door.nudging);
+    }
+
+    #[test]
+    fn buttons_only_work_while_open() {
+        let t0 = Instant::now();
+        let mut door = door();
+        assert_eq!(door.press_close(t0), None);
+        assert_eq!(door.hold(5000, t0), None);
+        door.open(0, t0);
+        assert_eq!(door.press_close(t0), None);
+        door.on_timer(door.seq, t0 + ms(1000));
+        assert_eq!(door.hold(5000, t0 + ms(1000)), Some(5000));
+        assert_eq!(door.press_close(t0 + ms(2000)), Some(2000));
+        assert_eq!(door.state, DoorState::Closing);
+    }
+}
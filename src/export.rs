@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use serde::Serialize;
+use crate::metrics::{CallRecord, Ride, WearRecord};
+
+// 导出的一条记录，可以写成 CSV 的一行，也可以写成一行 JSON
+pub trait Record: Serialize {
+    fn header() -> Vec<&'static str>;
+    fn fields(&self) -> Vec<String>;
+}
+
+impl Record for Ride {
+    fn header() -> Vec<&'static str> {
+        vec!["id", "origin", "destination", "direction", "lift",
+             "arrived_at", "picked_up_at", "boarded_at", "alighted_at",
+             "waiting", "riding", "total"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.origin.to_string(),
+            self.destination.to_string(),
+            format!("{:?}", self.direction),
+            self.lift.to_string(),
+            self.arrived_at.to_string(),
+            self.picked_up_at.to_string(),
+            self.boarded_at.to_string(),
+            self.alighted_at.to_string(),
+            self.waiting.to_string(),
+            self.riding.to_string(),
+            self.total.to_string(),
+        ]
+    }
+}
+
+impl Record for CallRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["floor", "direction", "lift", "called_at", "answered_at", "waiting"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.floor.to_string(),
+            format!("{:?}", self.direction),
+            self.lift.to_string(),
+            self.called_at.to_string(),
+            self.answered_at.to_string(),
+            self.waiting.to_string(),
+        ]
+    }
+}
+
+impl Record for WearRecord {
+    fn header() -> Vec<&'static str> {
+        vec!["lift", "floors_travelled", "starts", "stops", "door_cycles", "reversals",
+             "busy", "idle", "maintaining", "average_load_factor"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.lift.to_string(),
+            self.floors_travelled.to_string(),
+            self.starts.to_string(),
+            self.stops.to_string(),
+            self.door_cycles.to_string(),
+            self.reversals.to_string(),
+            self.busy.to_string(),
+            self.idle.to_string(),
+            self.maintaining.to_string(),
+            self.average_load_factor.to_string(),
+        ]
+    }
+}
+
+// 批量模拟的记录，前面加上调度策略和随机种子
+#[derive(Serialize)]
+pub struct Tagged<'a, T> {
+    pub dispatch: &'static str,
+    pub seed: u64,
+    #[serde(flatten)]
+    pub record: &'a T,
+}
+
+impl<'a, T: Record> Record for Tagged<'a, T> {
+    fn header() -> Vec<&'static str> {
+        let mut header = vec!["dispatch", "seed"];
+        header.extend(T::header());
+        header
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let mut fields = vec![self.dispatch.to_string(), self.seed.to_string()];
+        fields.extend(self.record.fields());
+        fields
+    }
+}
+
+// 导出文件的格式
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Csv,
+    // 每行一个 JSON
+    JsonLines,
+    // 所有记录写成一个 JSON 数组
+    Json,
+}
+
+impl Format {
+    fn of(path: &str) -> Self {
+        if path.ends_with(".jsonl") {
+            Format::JsonLines
+        } else if path.ends_with(".json") {
+            Format::Json
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+// 按文件的扩展名决定格式，.jsonl 每行一个 JSON，.json 写成一个 JSON 数组，其他的写成 CSV
+// 返回写入的记录数
+pub fn export<'a, R: Record + 'a, I: IntoIterator<Item=&'a R>>(path: &str, records: I) -> Result<usize, String> {
+    let file = File::create(path)
+        .map_err(|e| format!("导出文件{}创建失败: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+    let format = Format::of(path);
+    let write_err = |e: std::io::Error| format!("导出文件{}写入失败: {}", path, e);
+    match format {
+        Format::Csv => writeln!(writer, "{}", R::header().join(",")).map_err(write_err)?,
+        Format::Json => write!(writer, "[").map_err(write_err)?,
+        Format::JsonLines => {}
+    }
+    let mut n = 0;
+    for record in records {
+        if format == Format::Csv {
+            writeln!(writer, "{}", record.fields().join(",")).map_err(write_err)?;
+        } else {
+            if format == Format::Json {
+                // 一条记录一行，方便直接查看
+                write!(writer, "{}\n  ", if n == 0 { "" } else { "," }).map_err(write_err)?;
+            }
+            serde_json::to_writer(&mut writer, record)
+                .map_err(|e| format!("导出记录序列化失败: {}", e))?;
+            if format == Format::JsonLines {
+                writeln!(writer).map_err(write_err)?;
+            }
+        }
+        n += 1;
+    }
+    if format == Format::Json {
+        writeln!(writer, "{}]", if n == 0 { "" } else { "\n" }).map_err(write_err)?;
+    }
+    writer.flush().map_err(write_err)?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        floor: i32,
+        waiting: f64,
+    }
+
+    impl Record for Row {
+        fn header() -> Vec<&'static str> {
+            vec!["floor", "waiting"]
+        }
+
+        fn fields(&self) -> Vec<String> {
+            vec![self.floor.to_string(), self.waiting.to_string()]
+        }
+    }
+
+    fn rows() -> Vec<Row> {
+        vec![Row { floor: 3, waiting: 1.5 }, Row { floor: -1, waiting: 20.0 }]
+    }
+
+    // 导出到临时文件，返回文件的内容
+    fn exported(ext: &str, rows: &[Row]) -> String {
+        let path = std::env::temp_dir()
+            .join(format!("export-{}.{}", std::process::id(), ext))
+            .to_string_lossy()
+            .into_owned();
+        assert_eq!(export(&path, rows).unwrap(), rows.len());
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        content
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(exported("csv", &rows()), "floor,waiting\n3,1.5\n-1,20\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        assert_eq!(exported("jsonl", &rows()), "{\"floor\":3,\"waiting\":1.5}\n{\"floor\":-1,\"waiting\":20.0}\n");
+    }
+
+    #[test]
+    fn json_array() {
+        let content = exported("json", &rows());
+        let values: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1]["floor"], -1);
+        assert_eq!(values[1]["waiting"], 20.0);
+        let empty: Vec<serde_json::Value> = serde_json::from_str(&exported("json", &[])).unwrap();
+        assert!(empty.is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use std::fmt::{Display, Formatter};
 use iced::*;
 use iced::button::StyleSheet;
+use crate::building::building;
 use crate::icon::*;
-use crate::conf::{MAX_FLOOR, MIN_FLOOR, TFloor};
+use crate::conf::TFloor;
 use crate::message::AppMessage;
 use crate::style::{ActiveFloorBtnStyle, ActiveFloorTxtStyle};
+use serde::{Deserialize, Serialize};
 
 #[derive(Default)]
 pub struct FloorBtnState {
@@ -20,7 +22,7 @@ pub struct FloorBtnState {
 
 impl FloorBtnState {
     pub fn floor_view(&mut self) -> Element<AppMessage> {
-        let mut txt = Text::new(format!("{}", self.floor))
+        let mut txt = Text::new(building().name(self.floor))
             .horizontal_alignment(HorizontalAlignment::Center);
         if self.can_click {
             txt = txt.color(Color::from_rgb8(255, 63, 51 ));
@@ -43,7 +45,7 @@ impl FloorBtnState {
     }
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Debug)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -64,7 +66,7 @@ impl Display for Direction {
     }
 }
 
-#[derive(Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct WaitFloorTxtState {
     pub floor: TFloor,
     pub direction: Direction,
@@ -88,7 +90,7 @@ impl WaitFloorTxtState {
         let color = self.my_color();
         Container::new(
             Row::with_children(vec![
-                Text::new(format!("{}", self.floor))
+                Text::new(building().name(self.floor))
                     .color(color)
                     .horizontal_alignment(HorizontalAlignment::Center).into(),
                 match self.direction {
@@ -1,14 +1,52 @@
-use std::cmp::{max, min, Ordering};
-use std::collections::{BTreeMap, BTreeSet, HashMap, LinkedList};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
-use crate::conf::{EVERY_FLOOR_RUN_TIME_IN_MILLISECONDS, MAX_ELEVATOR_NUM, MAX_FLOOR, MAX_PERSON_CAPACITY, MIN_FLOOR, TFloor};
+use std::time::Instant;
+use rand::{Rng, thread_rng};
+use serde::{Deserialize, Serialize};
+use crate::building::building;
+use crate::config::{config, Config};
+use crate::conf::TFloor;
+use crate::door::{Door, DoorState};
+use iced::button;
 use crate::floor_btn::{Direction, FloorBtnState};
-use crate::message::AppMessage;
 use crate::state::State;
-use tokio::sync::Mutex;
-use std::sync::Arc;
-use crate::util::{random_bool, random_person_num};
+use crate::motion::{MotionProfile, Trip};
+use crate::passenger::Passenger;
+use crate::util::{all_floors, floor_position, next_floor};
 
+// 电梯的使用和磨损统计，用来安排维保、找出用得太多的电梯
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Wear {
+    // 运行经过的楼层数
+    pub floors_travelled: u64,
+    // 启动、停车的次数
+    pub starts: u64,
+    pub stops: u64,
+    // 开关门的次数，重新开门不算
+    pub door_cycles: u64,
+    // 运行方向反转的次数
+    pub reversals: u64,
+    // 忙碌、空闲、维护中的时间, 单位：秒
+    pub busy: f64,
+    pub idle: f64,
+    pub maintaining: f64,
+    // 载荷率按时间累计，除以总时间就是平均载荷率
+    pub load_time: f64,
+    // 上一次运行的方向
+    pub last_direction: Option<Direction>,
+}
+
+impl Wear {
+    // 统计以来的平均载荷率
+    pub fn average_load_factor(&self) -> f64 {
+        let total = self.busy + self.idle + self.maintaining;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.load_time / total
+    }
+}
 
 // 电梯
 #[derive(Default)]
@@ -17,10 +55,24 @@ pub struct Lift {
     pub no: usize,
     // 电梯运行状态
     pub state: State,
-    // 电梯内所搭载的人数
-    pub persons: i32,
+    // 电梯内所搭载的乘客
+    pub riders: Vec<Passenger>,
+    // 最大承载人数
+    pub capacity: usize,
+    // 额定载重, 单位：千克
+    pub rated_load: f64,
     // 电梯当前停靠楼层
     pub cur_floor: TFloor,
+    // 电梯的实时高度, 单位：米
+    pub position: f32,
+    // 电梯的运动参数
+    pub profile: MotionProfile,
+    // 运行中修改的运动参数，下一趟行程开始时生效
+    pub pending_profile: Option<MotionProfile>,
+    // 停靠的楼层，不停靠的楼层只会路过
+    pub floors: BTreeSet<TFloor>,
+    // 正在进行的行程
+    pub trip: Option<Trip>,
     pub can_click_btn: bool,
     // 用户输入的停靠楼层
     pub stop_floors: BTreeMap<TFloor, Option<Direction>>,
@@ -30,33 +82,77 @@ pub struct Lift {
     pub schedule_floors: BTreeMap<TFloor, Option<Direction>>,
     // 电梯里的按钮
     pub elevator_btns: Vec<FloorBtnState>,
+    // 电梯门
+    pub door: Door,
+    // 使用和磨损统计
+    pub wear: Wear,
+    // 维护按钮
+    pub maintain_btn_state: button::State,
+    // 开门、关门按钮
+    pub door_open_btn_state: button::State,
+    pub door_close_btn_state: button::State,
 }
-lazy_static!(
-    static ref LiftLocks: HashMap<usize,Arc<Mutex<bool>>> = {
-        let mut ret = HashMap::with_capacity(MAX_ELEVATOR_NUM);
-        for no in 0..MAX_ELEVATOR_NUM{
-            ret.insert(no, Arc::new(Mutex::new(true)));
-        }
-        ret
-    };
-);
 
 impl Lift {
     pub fn new(no: usize) -> Self {
         let mut r = Self::default();
         r.no = no;
-        r.cur_floor = crate::util::random_floor();
-        r.elevator_btns = (MIN_FLOOR..=MAX_FLOOR)
-            .into_iter()
-            .filter(|o| *o != 0)
+        let config = config();
+        r.apply_config(&config);
+        r.set_floors(config.lift_specs
+            .get(no)
+            .and_then(|spec| spec.floors.clone())
+            .unwrap_or_else(all_floors));
+        r.cur_floor = r.random_floor(&mut thread_rng());
+        r.position = floor_position(r.cur_floor);
+        r
+    }
+
+    // 按配置设置载客量、运动参数和开关门时间，配置里没有单独列出的电梯用统一的参数
+    // 正在运行的电梯不能中途换运动参数，等下一趟行程再换
+    pub fn apply_config(&mut self, config: &Config) {
+        let spec = config.lift_specs.get(self.no).cloned().unwrap_or_default();
+        self.capacity = spec.capacity.unwrap_or(config.capacity);
+        self.rated_load = spec.rated_load.unwrap_or(config.rated_load);
+        self.door.timings = spec.door.unwrap_or(config.door);
+        let mut profile = config.motion;
+        if let Some(speed) = spec.rated_speed {
+            profile.rated_speed = speed;
+        }
+        if self.trip.is_some() {
+            self.pending_profile = Some(profile);
+        } else {
+            self.profile = profile;
+            self.pending_profile = None;
+        }
+    }
+
+    // 设置停靠的楼层，轿厢里只有这些楼层的按钮
+    pub fn set_floors<I: IntoIterator<Item=TFloor>>(&mut self, floors: I) {
+        self.floors = floors.into_iter().collect();
+        let no = self.no;
+        self.elevator_btns = self.floors
+            .iter()
             .map(|o|
                 {
                     let mut btn_state = FloorBtnState::default();
                     btn_state.elevator_no = no;
-                    btn_state.floor = o;
+                    btn_state.floor = *o;
                     btn_state
                 }).collect();
-        r
+    }
+
+    // 是否停靠 floor 楼层
+    pub fn serves(&self, floor: TFloor) -> bool {
+        self.floors.contains(&floor)
+    }
+
+    // 在停靠的楼层里随机选一层
+    pub fn random_floor<R: Rng>(&self, rng: &mut R) -> TFloor {
+        *self.floors
+            .iter()
+            .nth(rng.gen_range(0..self.floors.len()))
+            .unwrap()
     }
 
     pub fn set_lift_btn_click(&mut self) {
@@ -105,55 +201,208 @@ impl Lift {
         None
     }
 
+    // 准备运行到下一层楼
+    // 返回到达下一层楼需要的时间, 单位：豪秒
+    pub fn begin_leg(&mut self, now: Instant) -> u32 {
+        let dest = match self.dest_floor() {
+            Some(dest) if dest != self.cur_floor => dest,
+            _ => {
+                // 没有要去的楼层，电梯原地不动
+                self.end_trip();
+                return 0;
+            }
+        };
+        let direction = if dest > self.cur_floor { Direction::Up } else { Direction::Down };
+        if self.trip.is_none() {
+            self.wear.starts += 1;
+        }
+        if self.wear.last_direction.map_or(false, |d| d != direction) {
+            self.wear.reversals += 1;
+        }
+        self.wear.last_direction = Some(direction);
+        let from = floor_position(self.cur_floor);
+        let to = floor_position(next_floor(self.cur_floor, direction).unwrap_or(dest));
+        let target = floor_position(dest);
+        let new_trip = match &self.trip {
+            None => true,
+            // 改变了方向，或者已经越过了新的目标楼层，就是一趟新的行程
+            Some(trip) => (to - from) * (target - trip.origin) <= 0.0 || (from - trip.origin).abs() > (target - trip.origin).abs(),
+        };
+        if new_trip {
+            if let Some(profile) = self.pending_profile.take() {
+                self.profile = profile;
+            }
+            self.trip = Some(Trip::new(from));
+        }
+        let trip = self.trip.as_mut().unwrap();
+        trip.target = target;
+        let secs = trip.begin_leg(&self.profile, from, to, now);
+        (secs * 1000.0).round() as u32
+    }
+
+    // 电梯到达了某楼层
+    pub fn arrive_floor(&mut self, floor: TFloor, is_stop: bool) {
+        self.wear.floors_travelled += building().distance(self.cur_floor, floor) as u64;
+        self.cur_floor = floor;
+        self.position = floor_position(floor);
+        if is_stop {
+            self.end_trip();
+        }
+    }
+
+    // 行程结束，电梯停下来
+    pub fn end_trip(&mut self) {
+        if self.trip.take().is_some() {
+            self.wear.stops += 1;
+        }
+    }
+
+    // 开门，门关着时算一次开关门
+    // 返回开门需要的时间
+    pub fn open_door(&mut self, exchange: u32, now: Instant) -> Option<u32> {
+        if self.door.state == DoorState::Closed {
+            self.wear.door_cycles += 1;
+        }
+        self.door.open(exchange, now)
+    }
+
+    // 电梯忙不忙：在运行，或者门没关好
+    pub fn is_busy(&self) -> bool {
+        !matches!(self.state, State::Stop | State::Maintaining) || !self.door.is_closed()
+    }
+
+    // 按当前的状态和载荷累计 elapsed 秒，电梯的利用率也按这里累计的忙碌时间算
+    pub fn accrue(&mut self, elapsed: f64) {
+        if self.state == State::Maintaining {
+            self.wear.maintaining += elapsed;
+        } else if self.is_busy() {
+            self.wear.busy += elapsed;
+        } else {
+            self.wear.idle += elapsed;
+        }
+        self.wear.load_time += self.load_factor() * elapsed;
+    }
+
+    // 刷新电梯的实时高度
+    pub fn update_position(&mut self, now: Instant) {
+        if let Some(trip) = &self.trip {
+            self.position = trip.position(&self.profile, now);
+        }
+    }
+
+    // 估算电梯到达某楼层所需的时间, 单位：秒
+    // 要先等门关好，途中每停靠一层，都要加上一次开关门的时间，这一层要下电梯的人越多停得越久
+    pub fn eta(&self, floor: TFloor, now: Instant) -> f32 {
+        let target = floor_position(floor);
+        let (low, high) = if target >= self.position { (self.position, target) } else { (target, self.position) };
+        let mut stops = self.schedule_floors
+            .keys()
+            .chain(self.stop_floors.keys())
+            .copied()
+            .filter(|f| floor_position(*f) > low && floor_position(*f) < high)
+            .collect::<Vec<_>>();
+        stops.sort_by(|a, b| (floor_position(*a) - self.position).abs()
+            .partial_cmp(&(floor_position(*b) - self.position).abs())
+            .unwrap());
+        stops.dedup();
+        let cycles = stops
+            .iter()
+            .map(|f| self.door.timings.cycle(self.riders.iter().filter(|p| p.destination == *f).count() as u32))
+            .sum::<u32>();
+        let dwell = (self.door.remaining(now) + cycles) as f32 / 1000.0;
+        let stops = stops.into_iter().map(floor_position);
+        let mut from = self.position;
+        let mut total = dwell;
+        for stop in stops.chain(std::iter::once(target)) {
+            total += self.profile.travel_time((stop - from).abs());
+            from = stop;
+        }
+        total
+    }
+
+    // 估算电梯去接某楼层 direction 方向的呼叫所需的时间, 单位：秒
+    // 电梯正往反方向走，或者已经走过了这个楼层时，要先走完这个方向上的楼层再掉头
+    pub fn call_eta(&self, floor: TFloor, direction: Direction, now: Instant) -> f32 {
+        let target = floor_position(floor);
+        let turn = match self.state {
+            State::GoingUp | State::GoingUpSuspend if direction == Direction::Down || target < self.position => {
+                self.schedule_floors.keys().chain(self.stop_floors.keys()).max().copied()
+            }
+            State::GoingDown | State::GoingDownSuspend if direction == Direction::Up || target > self.position => {
+                self.schedule_floors.keys().chain(self.stop_floors.keys()).min().copied()
+            }
+            _ => None,
+        };
+        match turn {
+            Some(turn) if turn != floor => {
+                self.eta(turn, now)
+                    + self.door.timings.cycle(0) as f32 / 1000.0
+                    + self.profile.travel_time((floor_position(turn) - target).abs())
+            }
+            _ => self.eta(floor, now),
+        }
+    }
+
     pub fn remove_floor(&mut self, floor: TFloor) -> Option<Direction> {
         self.stop_floors.remove(&floor);
         self.schedule_floors.remove(&floor).unwrap_or(None)
     }
+    // 电梯内的人数
+    pub fn persons(&self) -> usize {
+        self.riders.len()
+    }
+
+    // 称重装置测得的载重, 单位：千克
+    pub fn load(&self) -> f64 {
+        self.riders.iter().map(|p| p.weight).sum()
+    }
+
+    // 超过额定载重，不能关门运行
     pub fn is_overload(&self) -> bool {
-        self.persons > MAX_PERSON_CAPACITY as i32
+        self.load() > self.rated_load
     }
 
-    pub fn set_persons(&mut self) {
-        let n = random_person_num();
-        if random_bool() {
-            self.persons += n;
-            self.persons = min(self.persons, MAX_PERSON_CAPACITY as i32);
-        } else {
-            self.persons -= n;
-            self.persons = max(self.persons, 0);
-        }
-    }
-
-    pub async fn suspend_one_by_one_floor(no: usize, is_wait: bool) -> AppMessage {
-        let lock = LiftLocks.get(&no).unwrap();
-        // 每部电梯一个锁， 从而保证消息的顺序性
-        lock.lock().await;
-        // 通过sleep ，模拟电梯在运行到了
-        if is_wait {
-            // 等待居民进出时，需要休眠更长时间
-            tokio::time::sleep(
-                std::time::Duration::from_millis(
-                    EVERY_FLOOR_RUN_TIME_IN_MILLISECONDS as u64 + 500)
-            ).await;
+    // 轿厢的载荷率，按称重的结果计算
+    pub fn load_factor(&self) -> f64 {
+        if self.rated_load > 0.0 {
+            self.load() / self.rated_load
         } else {
-            tokio::time::sleep(
-                std::time::Duration::from_millis(
-                    EVERY_FLOOR_RUN_TIME_IN_MILLISECONDS as u64)
-            ).await;
+            1.0
         }
+    }
+
+    // 超载时，最后进来的乘客退出电梯
+    pub fn step_out(&mut self) -> Option<Passenger> {
+        if !self.is_overload() {
+            return None;
+        }
+        let p = self.riders.pop()?;
+        if self.riders.iter().all(|o| o.destination != p.destination) {
+            self.stop_floors.remove(&p.destination);
+        }
+        Some(p)
+    }
+
+    // 还能进几个人
+    pub fn free_capacity(&self) -> usize {
+        self.capacity.saturating_sub(self.persons())
+    }
 
-        AppMessage::ArriveByOneFloor(no)
+    // 到达目的楼层的乘客离开电梯
+    pub fn alight(&mut self, floor: TFloor) -> Vec<Passenger> {
+        let (alighted, riders) = self.riders
+            .drain(..)
+            .partition(|p| p.destination == floor);
+        self.riders = riders;
+        alighted
     }
 
-    pub async fn user_input_one_by_one_floor(no: usize) -> AppMessage {
-        let lock = LiftLocks.get(&no).unwrap();
-        // 每部电梯一个锁， 从而保证消息的顺序性
-        lock.lock().await;
-        // 通过sleep ，模拟电梯在运行到了
-        tokio::time::sleep(
-            std::time::Duration::from_millis(500)
-        ).await;
-        AppMessage::WaitUserInputFloor(no)
+    // 乘客进入电梯，并按下自己的目的楼层
+    pub fn board(&mut self, passengers: Vec<Passenger>) {
+        for p in passengers {
+            self.stop_floors.insert(p.destination, None);
+            self.riders.push(p);
+        }
     }
 }
 
@@ -163,7 +412,7 @@ impl Display for Lift {
                "电梯#{}[{}层-{}人:{},click:{}]",
                self.no + 1,
                self.cur_floor,
-               self.persons,
+               self.persons(),
                self.state.to_string(),
                self.can_click_btn
         )
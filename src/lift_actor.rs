@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use iced::Subscription;
+use iced::futures::stream::{self, BoxStream, StreamExt};
+use iced_futures::subscription::Recipe;
+use tokio::time::Instant;
+use tokio::sync::{broadcast, mpsc};
+use crate::message::AppMessage;
+
+// 状态广播的缓冲大小，每部电梯同时最多只有一个运行和一个开关门定时器，够用了
+const STATUS_CAPACITY: usize = 64;
+
+// 每启动一个电梯任务加一，同一个序号的电梯换了新任务时，界面要重新订阅
+static NEXT_ACTOR_ID: AtomicU64 = AtomicU64::new(0);
+
+// 发给电梯任务的指令
+// 新的运行、开关门指令会替换掉还没到期的旧定时器，和批量模拟的事件队列一样
+pub enum LiftCommand {
+    // 运行一层楼，millis 是运行这层楼需要的时间，由电梯的运行曲线算出
+    RunOneFloor {
+        millis: u32,
+    },
+    // 开关门定时器
+    DoorTimer {
+        millis: u32,
+        seq: u32,
+    },
+    // 结束电梯任务，还没到期的定时器都作废
+    Quit,
+}
+
+// 电梯任务广播出来的状态，界面订阅后交给模拟处理
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LiftStatus {
+    // 电梯运行了一层楼
+    RanOneFloor(usize),
+    // 电梯门的定时器到期：电梯序号，门的状态序号
+    DoorTimer(usize, u32),
+}
+
+impl From<LiftStatus> for AppMessage {
+    fn from(status: LiftStatus) -> Self {
+        match status {
+            LiftStatus::RanOneFloor(no) => AppMessage::ArriveByOneFloor(no),
+            LiftStatus::DoorTimer(no, seq) => AppMessage::DoorTimer(no, seq),
+        }
+    }
+}
+
+// 电梯任务的句柄
+// 每部电梯一个任务、一个指令通道和一个状态广播，指令按发送的顺序依次执行，从而保证消息的顺序性
+#[derive(Clone)]
+pub struct LiftHandle {
+    pub no: usize,
+    id: u64,
+    commands: mpsc::UnboundedSender<LiftCommand>,
+    status: broadcast::Sender<LiftStatus>,
+    // 任务启动时就订阅好的接收端，界面的订阅开始之前广播的状态不会丢
+    first: Arc<Mutex<Option<broadcast::Receiver<LiftStatus>>>>,
+    // 已经要求结束，到期的定时器不再广播
+    quitting: Arc<AtomicBool>,
+}
+
+impl LiftHandle {
+    // 需要在 tokio 运行时里调用
+    pub fn spawn(no: usize) -> Self {
+        let (commands, rx) = mpsc::unbounded_channel();
+        let (status, first) = broadcast::channel(STATUS_CAPACITY);
+        let quitting = Arc::new(AtomicBool::new(false));
+        tokio::spawn(run(no, rx, status.clone(), quitting.clone()));
+        Self {
+            no,
+            id: NEXT_ACTOR_ID.fetch_add(1, Ordering::SeqCst),
+            commands,
+            status,
+            first: Arc::new(Mutex::new(Some(first))),
+            quitting,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LiftStatus> {
+        self.first
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| self.status.subscribe())
+    }
+
+    // 电梯任务已经结束时，指令发送失败，可以忽略
+    pub fn run_one_floor(&self, millis: u32) {
+        let _ = self.commands.send(LiftCommand::RunOneFloor { millis });
+    }
+
+    pub fn door_timer(&self, millis: u32, seq: u32) {
+        let _ = self.commands.send(LiftCommand::DoorTimer { millis, seq });
+    }
+
+    pub fn quit(&self) {
+        self.quitting.store(true, Ordering::SeqCst);
+        let _ = self.commands.send(LiftCommand::Quit);
+    }
+
+    // 订阅这部电梯的状态广播，转换成界面的消息
+    pub fn subscription(&self) -> Subscription<AppMessage> {
+        Subscription::from_recipe(StatusRecipe { handle: self.clone() }).map(AppMessage::from)
+    }
+}
+
+async fn run(no: usize,
+             mut commands: mpsc::UnboundedReceiver<LiftCommand>,
+             status: broadcast::Sender<LiftStatus>,
+             quitting: Arc<AtomicBool>) {
+    // 正在运行的楼层，以及还没到期的开关门定时器
+    // 都作为 select 的分支等待，运行楼层时也能马上收到新的指令
+    let mut running: Option<Instant> = None;
+    let mut door: Option<(Instant, u32)> = None;
+    loop {
+        let deadline = door.map(|(at, _)| at);
+        tokio::select! {
+            cmd = commands.recv() => match cmd {
+                Some(LiftCommand::RunOneFloor { millis }) => {
+                    // 通过定时器，模拟电梯在运行
+                    running = Some(Instant::now() + Duration::from_millis(millis as u64));
+                }
+                Some(LiftCommand::DoorTimer { millis, seq }) => {
+                    door = Some((Instant::now() + Duration::from_millis(millis as u64), seq));
+                }
+                Some(LiftCommand::Quit) | None => break,
+            },
+            _ = tokio::time::sleep_until(running.unwrap_or_else(Instant::now)), if running.is_some() => {
+                running = None;
+                if quitting.load(Ordering::SeqCst) {
+                    // 电梯已经被移除或替换
+                    break;
+                }
+                // 没有订阅者时发送会失败，可以忽略
+                let _ = status.send(LiftStatus::RanOneFloor(no));
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                if let Some((_, seq)) = door.take() {
+                    if quitting.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let _ = status.send(LiftStatus::DoorTimer(no, seq));
+                }
+            }
+        }
+    }
+}
+
+// 界面对一个电梯任务状态广播的订阅，任务换了就是新的订阅
+struct StatusRecipe {
+    handle: LiftHandle,
+}
+
+impl<H: Hasher, I> Recipe<H, I> for StatusRecipe {
+    type Output = LiftStatus;
+
+    fn hash(&self, state: &mut H) {
+        std::any::TypeId::of::<Self>().hash(state);
+        self.handle.id.hash(state);
+    }
+
+    fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Self::Output> {
+        let rx = self.handle.subscribe();
+        stream::unfold(rx, |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(status) => return Some((status, rx)),
+                    // 界面处理不过来，丢了一些状态，接着收后面的
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    // 电梯任务结束了
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }).boxed()
+    }
+}
+
+// 所有电梯任务，以电梯序号为键，电梯的数量可以在运行时变化
+#[derive(Default)]
+pub struct LiftActors {
+    handles: HashMap<usize, LiftHandle>,
+}
+
+impl LiftActors {
+    pub fn spawn(&mut self, no: usize) -> &LiftHandle {
+        if let Some(old) = self.handles.insert(no, LiftHandle::spawn(no)) {
+            old.quit();
+        }
+        &self.handles[&no]
+    }
+
+    pub fn remove(&mut self, no: usize) {
+        if let Some(handle) = self.handles.remove(&no) {
+            handle.quit();
+        }
+    }
+
+    pub fn run_one_floor(&self, no: usize, millis: u32) {
+        if let Some(handle) = self.handles.get(&no) {
+            handle.run_one_floor(millis);
+        }
+    }
+
+    pub fn door_timer(&self, no: usize, millis: u32, seq: u32) {
+        if let Some(handle) = self.handles.get(&no) {
+            handle.door_timer(millis, seq);
+        }
+    }
+
+    // 所有电梯任务的状态广播
+    pub fn subscription(&self) -> Subscription<AppMessage> {
+        Subscription::batch(self.handles.values().map(LiftHandle::subscription))
+    }
+}
+
+impl Drop for LiftActors {
+    fn drop(&mut self) {
+        self.handles.values().for_each(|h| h.quit());
+    }
+}
@@ -12,16 +12,227 @@ pub mod style;
 pub mod floor_btn;
 pub mod icon;
 pub mod lift;
+pub mod lift_actor;
+pub mod motion;
+pub mod door;
+pub mod passenger;
+pub mod traffic;
+pub mod profile;
+pub mod cli;
+pub mod snapshot;
+pub mod trace;
 pub mod scheduler2;
 pub mod util;
+pub mod simulation;
+pub mod stats;
+pub mod batch;
+pub mod dispatch;
+pub mod building;
+pub mod config;
+pub mod metrics;
+pub mod export;
+pub mod charts;
+pub mod rtt;
 
 use std::io::{Read, Write};
 // use scheduler::Scheduler;
 
+// 导出批量模拟的乘客行程、呼叫记录和电梯磨损统计，包括预热期的，可以按时刻自己过滤
+fn export_batch(opts: &cli::Options, dispatches: &[dispatch::Dispatch], results: &[Vec<batch::Replication>]) {
+    let runs = || dispatches
+        .iter()
+        .zip(results.iter())
+        .flat_map(|(dispatch, reps)| reps.iter().map(move |r| (dispatch.name(), r)));
+    let rides = runs()
+        .flat_map(|(dispatch, r)| r.finished
+            .iter()
+            .filter_map(metrics::Ride::of)
+            .map(move |ride| (dispatch, r.seed, ride)))
+        .collect::<Vec<_>>();
+    let trips = rides
+        .iter()
+        .map(|(dispatch, seed, ride)| export::Tagged { dispatch, seed: *seed, record: ride })
+        .collect::<Vec<_>>();
+    let calls = runs()
+        .flat_map(|(dispatch, r)| r.calls
+            .iter()
+            .map(move |call| export::Tagged { dispatch, seed: r.seed, record: call }))
+        .collect::<Vec<_>>();
+    let wear = runs()
+        .flat_map(|(dispatch, r)| r.wear
+            .iter()
+            .enumerate()
+            .map(move |(no, wear)| (dispatch, r.seed, metrics::WearRecord::of(no, wear))))
+        .collect::<Vec<_>>();
+    let wear = wear
+        .iter()
+        .map(|(dispatch, seed, record)| export::Tagged { dispatch, seed: *seed, record })
+        .collect::<Vec<_>>();
+    let results = [
+        ("乘客行程", &opts.trips, export::export(&opts.trips, &trips)),
+        ("呼叫记录", &opts.calls, export::export(&opts.calls, &calls)),
+        ("电梯磨损统计", &opts.wear, export::export(&opts.wear, &wear)),
+    ];
+    for (what, path, result) in results {
+        match result {
+            Ok(n) => println!("{}条{}已导出到{}", n, what, path),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+// 不打开窗口，批量模拟后打印统计结果
+fn run_batch(opts: &cli::Options, n: usize) {
+    use rand::Rng;
+    let config = config::config();
+    let scenario = batch::Scenario {
+        lifts: opts.lifts.unwrap_or(config.lifts),
+        profile: opts.profile.unwrap_or_default(),
+        intensity: opts.intensity.unwrap_or(config.traffic.intensity),
+        dispatch: opts.dispatch,
+        duration: opts.duration,
+        behaviour: config.passengers,
+    };
+    let seed = opts.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let threads = opts.threads.unwrap_or_else(|| std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1));
+    println!("批量模拟{}次：客流:{}，强度:{:.1}%，{}部电梯，每次{:.0}秒，随机种子从{}开始，{}个线程",
+             n, scenario.profile, scenario.intensity, scenario.lifts, scenario.duration, seed, threads);
+    let started = std::time::Instant::now();
+    if !opts.compare.is_empty() {
+        // 每种策略用同样的种子，乘客完全一样
+        let results = opts.compare
+            .iter()
+            .map(|dispatch| {
+                let scenario = batch::Scenario { dispatch: *dispatch, ..scenario.clone() };
+                batch::run_batch(&scenario, n, seed, threads)
+            })
+            .collect::<Vec<_>>();
+        println!("用时{:.1}秒", started.elapsed().as_secs_f64());
+        export_batch(opts, &opts.compare, &results);
+        let (warmup, results) = batch::apply_warmup(&results, opts.warmup);
+        println!("去掉前{:.0}秒的预热期", warmup);
+        return batch::ComparisonReport::new(&opts.compare, &results).print();
+    }
+    println!("调度策略:{}", scenario.dispatch);
+    let results = vec![batch::run_batch(&scenario, n, seed, threads)];
+    export_batch(opts, &[scenario.dispatch], &results);
+    let (warmup, mut results) = batch::apply_warmup(&results, opts.warmup);
+    let results = results.remove(0);
+    println!("去掉前{:.0}秒的预热期", warmup);
+    println!("用时{:.1}秒，共运送{}人", started.elapsed().as_secs_f64(),
+             results.iter().map(|r| r.delivered).sum::<usize>());
+    batch::BatchReport::new(&results).print();
+    if opts.metrics {
+        println!();
+        println!("所有模拟合起来，按方向、电梯、出发楼层分组的乘客指标：");
+        metrics::Metrics::from_passengers(results
+            .iter()
+            .flat_map(|r| r.finished.iter().filter(move |p| p.arrived_at >= r.warmup)))
+            .print();
+    }
+    if let Some(dir) = &opts.charts {
+        match charts::render_all(dir, opts.chart_format, &results) {
+            Ok(files) => println!("图表已保存: {}", files.join(", ")),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+// 早高峰往返时间和运送能力的计算，加上 --batch 时再用早高峰客流的模拟对照
+fn run_rtt(opts: &cli::Options) {
+    use rand::Rng;
+    let config = config::config();
+    let lifts = opts.lifts.unwrap_or(config.lifts);
+    let calc = match rtt::UpPeak::calculate(&config, lifts) {
+        Ok(calc) => calc,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    calc.print();
+    let n = match opts.batch {
+        Some(n) => n,
+        None => return,
+    };
+    // 早高峰客流只去大厅以上的楼层，到达率也不随时间变化，和计算的假设一样
+    let lobby = config.building.lobby;
+    if !config.traffic.rates.is_empty() {
+        let mut constant = (*config).clone();
+        constant.traffic.rates.clear();
+        constant.traffic.period = None;
+        config::set_config(constant);
+    }
+    // 客流强度取计算出的运送能力，电梯正好满负荷
+    let scenario = batch::Scenario {
+        lifts,
+        profile: profile::TrafficProfile::UpPeak,
+        intensity: calc.percent_population,
+        dispatch: opts.dispatch,
+        duration: opts.duration,
+        behaviour: config.passengers,
+    };
+    let seed = opts.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let threads = opts.threads.unwrap_or_else(|| std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1));
+    println!();
+    println!("早高峰模拟{}次：强度:{:.1}%，调度策略:{}，每次{:.0}秒，随机种子从{}开始",
+             n, scenario.intensity, scenario.dispatch, scenario.duration, seed);
+    let results = batch::run_batch(&scenario, n, seed, threads);
+    let (warmup, mut results) = batch::apply_warmup(&[results], opts.warmup);
+    println!("去掉前{:.0}秒的预热期", warmup);
+    rtt::print_comparison(&calc, &rtt::Observed::of(&results.remove(0), lobby));
+}
+
+// 不打开窗口回放输入轨迹，有偏差时退出码为 1
+fn run_replay(path: &str) {
+    let (snapshot, mut replay) = match trace::Replay::load(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    println!("开始回放输入轨迹{}，共{}个输入", path, replay.events.len());
+    replay.run(snapshot);
+    if replay.divergences > 0 {
+        std::process::exit(1);
+    }
+}
+
 fn main() {
    // let mut schedule = Scheduler::new();
    //  schedule.run();
-    app::run_window()
+    let opts = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    // 配置要在创建电梯之前载入
+    if let Some(path) = &opts.config {
+        match config::Config::load(path) {
+            Ok(config) => config::set_config(config),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    if opts.rtt {
+        return run_rtt(&opts);
+    }
+    if let Some(path) = &opts.replay {
+        return run_replay(path);
+    }
+    match opts.batch {
+        Some(n) => run_batch(&opts, n),
+        None => app::run_window(opts),
+    }
     // for x in "尾是发发发fy̆发发发".chars(){
     //     println!("{}", x);
     // }
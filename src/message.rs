@@ -1,5 +1,5 @@
 use crate::conf::TFloor;
-use crate::floor_btn::Direction;
+use crate::profile::TrafficProfile;
 
 #[derive(Clone)]
 pub enum Message {
@@ -22,24 +22,53 @@ pub enum Message {
 #[derive(Clone, Copy, Debug, PartialOrd, PartialEq)]
 pub enum AppMessage {
     Noop,
+    // 兜底调度
     Scheduling,
-    // 调度中
-    Scheduling2(TFloor, Direction),
-    // Scheduled2(TFloor, Direction),
     Scheduled,
     // 调度完成
-    SliderChange(TFloor),
+    // 滑块移动到的楼层索引
+    SliderChange(i32),
+    // 滑块松开时所选的楼层
     SliderRelease(TFloor),
     // 一层层的到达楼层
     ArriveByOneFloor(usize),
-    // 到达某楼层之后等待用户的输入
-    WaitUserInputFloor(usize),
-    LiftRunning,
+    // 电梯门的定时器到期，电梯序号和定时器序号
+    DoorTimer(usize, u32),
+    // 刷新电梯的实时位置
+    Tick,
     ClickedBtnPlus,
     ClickedBtnSubtract,
     ClickedBtnUp,
     ClickedBtnDown,
     ClickedBtnFloor(usize, TFloor),
+    // 切换电梯的维护状态
+    ClickedBtnMaintain(usize),
+    // 电梯里的开门、关门按钮
+    ClickedBtnDoorOpen(usize),
+    ClickedBtnDoorClose(usize),
+    // 开启、关闭自动客流
+    ClickedBtnTraffic,
+    // 自动客流有乘客到达，参数是生成器的序号
+    TrafficArrival(u32),
+    // 选择客流、调整客流强度
+    SelectTrafficProfile(TrafficProfile),
+    TrafficIntensityChange(f64),
+    // 短途乘客等得太久时是否改走楼梯
+    ToggleStairs(bool),
+    // 定时检查候梯的乘客
+    PassengerTick,
+    // 保存、载入快照
+    ClickedBtnSaveSnapshot,
+    ClickedBtnLoadSnapshot,
+    // 导出乘客行程和呼叫记录
+    ClickedBtnExport,
+    // 运行时增加、减少电梯
+    ClickedBtnAddLift,
+    ClickedBtnRemoveLift,
+    // 定时检查配置文件有没有修改
+    CheckConfig,
+    // 仪表盘重新开始统计
+    ClickedBtnResetMetrics,
 }
 
 impl Default for Message {
@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::building::building;
+use crate::conf::TFloor;
+use crate::floor_btn::Direction;
+use crate::lift::{Lift, Wear};
+use crate::passenger::Passenger;
+use crate::simulation::Simulation;
+use crate::stats::{mean, percentile};
+
+// 一位乘客坐了一趟电梯, 时间的单位：秒
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ride {
+    // 乘客编号
+    pub id: usize,
+    pub origin: TFloor,
+    pub destination: TFloor,
+    pub direction: Direction,
+    // 乘坐的电梯序号
+    pub lift: usize,
+    // 到达候梯厅、电梯到站、进入轿厢、走出轿厢的时刻
+    pub arrived_at: f64,
+    pub picked_up_at: f64,
+    pub boarded_at: f64,
+    pub alighted_at: f64,
+    // 从到达候梯厅呼梯，到接他的电梯到站
+    pub waiting: f64,
+    // 从进入轿厢到走出轿厢
+    pub riding: f64,
+    // 从到达候梯厅到走出轿厢
+    pub total: f64,
+}
+
+impl Ride {
+    // 走楼梯的、还没走出轿厢的乘客没有乘梯记录
+    pub fn of(p: &Passenger) -> Option<Self> {
+        if p.took_stairs {
+            return None;
+        }
+        let boarded_at = p.boarded_at?;
+        let alighted_at = p.alighted_at?;
+        let picked_up_at = p.picked_up_at?;
+        Some(Self {
+            id: p.id,
+            origin: p.origin,
+            destination: p.destination,
+            direction: p.direction(),
+            lift: p.lift?,
+            arrived_at: p.arrived_at,
+            picked_up_at,
+            boarded_at,
+            alighted_at,
+            waiting: picked_up_at - p.arrived_at,
+            riding: alighted_at - boarded_at,
+            total: alighted_at - p.arrived_at,
+        })
+    }
+}
+
+// 一次候梯厅的呼叫，从按下按钮到被派去的电梯到站, 时间的单位：秒
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CallRecord {
+    pub floor: TFloor,
+    pub direction: Direction,
+    // 应答呼叫的电梯序号
+    pub lift: usize,
+    pub called_at: f64,
+    pub answered_at: f64,
+    pub waiting: f64,
+}
+
+// 一部电梯的使用和磨损统计，时间的单位：秒
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct WearRecord {
+    pub lift: usize,
+    pub floors_travelled: u64,
+    pub starts: u64,
+    pub stops: u64,
+    pub door_cycles: u64,
+    pub reversals: u64,
+    pub busy: f64,
+    pub idle: f64,
+    pub maintaining: f64,
+    pub average_load_factor: f64,
+}
+
+impl WearRecord {
+    pub fn of(lift: usize, wear: &Wear) -> Self {
+        Self {
+            lift,
+            floors_travelled: wear.floors_travelled,
+            starts: wear.starts,
+            stops: wear.stops,
+            door_cycles: wear.door_cycles,
+            reversals: wear.reversals,
+            busy: wear.busy,
+            idle: wear.idle,
+            maintaining: wear.maintaining,
+            average_load_factor: wear.average_load_factor(),
+        }
+    }
+}
+
+// 一组时间的统计量, 单位：秒
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Kpi {
+    pub n: usize,
+    pub mean: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Kpi {
+    pub fn of(samples: &[f64]) -> Self {
+        Self {
+            n: samples.len(),
+            mean: mean(samples),
+            max: samples.iter().copied().fold(f64::NAN, f64::max),
+            p50: percentile(samples, 50.0),
+            p90: percentile(samples, 90.0),
+            p95: percentile(samples, 95.0),
+            p99: percentile(samples, 99.0),
+        }
+    }
+}
+
+// 候梯、乘梯、全程三种时间的统计
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RideKpi {
+    pub waiting: Kpi,
+    pub riding: Kpi,
+    pub total: Kpi,
+}
+
+impl RideKpi {
+    pub fn of<'a, I: IntoIterator<Item=&'a Ride>>(rides: I) -> Self {
+        let rides = rides.into_iter().collect::<Vec<_>>();
+        let kpi = |f: fn(&Ride) -> f64| Kpi::of(&rides.iter().map(|r| f(r)).collect::<Vec<_>>());
+        Self {
+            waiting: kpi(|r| r.waiting),
+            riding: kpi(|r| r.riding),
+            total: kpi(|r| r.total),
+        }
+    }
+}
+
+// 乘客级别的服务指标，每位乘完电梯的乘客记一条
+// 另外记下每次应答了的呼叫，用来导出
+// 快照里要保存下来，载入后接着统计
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    rides: Vec<Ride>,
+    calls: Vec<CallRecord>,
+    // 还没应答的呼叫按下的时刻
+    #[serde(with = "open_calls")]
+    open_calls: BTreeMap<(TFloor, Direction), f64>,
+}
+
+// JSON 的键只能是字符串，还没应答的呼叫存成 (楼层, 方向, 按下的时刻) 的列表
+mod open_calls {
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use crate::conf::TFloor;
+    use crate::floor_btn::Direction;
+
+    pub fn serialize<S: Serializer>(calls: &BTreeMap<(TFloor, Direction), f64>, serializer: S) -> Result<S::Ok, S::Error> {
+        calls
+            .iter()
+            .map(|((floor, direction), at)| (*floor, *direction, *at))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<(TFloor, Direction), f64>, D::Error> {
+        Ok(Vec::<(TFloor, Direction, f64)>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(floor, direction, at)| ((floor, direction), at))
+            .collect())
+    }
+}
+
+impl Metrics {
+    pub fn from_passengers<'a, I: IntoIterator<Item=&'a Passenger>>(passengers: I) -> Self {
+        Self {
+            rides: passengers
+                .into_iter()
+                .filter_map(Ride::of)
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    // 记录一位走出轿厢的乘客
+    pub fn record(&mut self, p: &Passenger) {
+        if let Some(ride) = Ride::of(p) {
+            self.rides.push(ride);
+        }
+    }
+
+    pub fn rides(&self) -> &[Ride] {
+        &self.rides
+    }
+
+    // 候梯厅有人呼梯
+    pub fn call(&mut self, floor: TFloor, direction: Direction, at: f64) {
+        self.open_calls.entry((floor, direction)).or_insert(at);
+    }
+
+    // 电梯到站应答了呼叫
+    // 载入快照前按下的呼叫没有记下时刻，不记录
+    pub fn answer(&mut self, floor: TFloor, direction: Direction, lift: usize, at: f64) {
+        if let Some(called_at) = self.open_calls.remove(&(floor, direction)) {
+            self.calls.push(CallRecord {
+                floor,
+                direction,
+                lift,
+                called_at,
+                answered_at: at,
+                waiting: at - called_at,
+            });
+        }
+    }
+
+    // 候梯的人都走了，呼叫取消，不算应答
+    pub fn cancel(&mut self, floor: TFloor, direction: Direction) {
+        self.open_calls.remove(&(floor, direction));
+    }
+
+    pub fn calls(&self) -> &[CallRecord] {
+        &self.calls
+    }
+
+    pub fn take_calls(&mut self) -> Vec<CallRecord> {
+        std::mem::take(&mut self.calls)
+    }
+
+    // 只清空乘梯记录，重新开始统计，呼叫记录留着导出
+    pub fn clear(&mut self) {
+        self.rides.clear();
+    }
+
+    // 所有乘客
+    pub fn overall(&self) -> RideKpi {
+        RideKpi::of(&self.rides)
+    }
+
+    // 按出发楼层
+    pub fn by_floor(&self) -> BTreeMap<TFloor, RideKpi> {
+        self.group_by(|r| r.origin)
+    }
+
+    // 按上行、下行
+    pub fn by_direction(&self) -> BTreeMap<Direction, RideKpi> {
+        self.group_by(|r| r.direction)
+    }
+
+    // 按乘坐的电梯
+    pub fn by_lift(&self) -> BTreeMap<usize, RideKpi> {
+        self.group_by(|r| r.lift)
+    }
+
+    fn group_by<K: Ord, F: Fn(&Ride) -> K>(&self, key: F) -> BTreeMap<K, RideKpi> {
+        let mut groups = BTreeMap::<K, Vec<&Ride>>::new();
+        for ride in &self.rides {
+            groups.entry(key(ride)).or_default().push(ride);
+        }
+        groups
+            .into_iter()
+            .map(|(k, rides)| (k, RideKpi::of(rides)))
+            .collect()
+    }
+
+    // 打印全部、按方向、按电梯、按楼层的统计表
+    pub fn print(&self) {
+        let building = building();
+        println!("{:<10}{:>6}{:^48}{:^48}{:^48}", "", "", "候梯时间(秒)", "乘梯时间(秒)", "全程时间(秒)");
+        let stats = ["均值", "P50", "P90", "P95", "P99", "最大"];
+        println!("{:<10}{:>6}{}", "分组", "人数", stats
+            .iter()
+            .chain(stats.iter())
+            .chain(stats.iter())
+            .map(|s| format!("{:>8}", s))
+            .collect::<String>());
+        let row = |name: String, k: &RideKpi| {
+            let cols = [k.waiting, k.riding, k.total]
+                .iter()
+                .flat_map(|kpi| [kpi.mean, kpi.p50, kpi.p90, kpi.p95, kpi.p99, kpi.max])
+                .map(|v| format!("{:>8.1}", v))
+                .collect::<String>();
+            println!("{:<10}{:>6}{}", name, k.waiting.n, cols);
+        };
+        row("全部".to_string(), &self.overall());
+        for (direction, k) in self.by_direction() {
+            row(format!("{}行", direction), &k);
+        }
+        for (no, k) in self.by_lift() {
+            row(format!("电梯#{}", no + 1), &k);
+        }
+        for (floor, k) in self.by_floor() {
+            row(format!("{}层出发", building.name(floor)), &k);
+        }
+    }
+}
+
+// 每部电梯的利用率：统计开始以来忙碌的时间所占的比例
+// 忙碌的时间只在电梯的使用统计 Wear 里累计，这里只记下开始统计时每部电梯已经忙了多久
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Utilisation {
+    // 开始统计的时刻
+    pub since: f64,
+    // 电梯的使用统计累计到的时刻
+    last: f64,
+    // 开始统计时每部电梯已经忙碌的时间, 单位：秒
+    baseline: Vec<f64>,
+}
+
+impl Utilisation {
+    pub fn new(lifts: &[Lift], clock: f64) -> Self {
+        Self {
+            since: clock,
+            last: clock,
+            baseline: lifts.iter().map(|lift| lift.wear.busy).collect(),
+        }
+    }
+
+    // 从 clock 时刻重新开始统计
+    pub fn reset(&mut self, lifts: &[Lift], clock: f64) {
+        *self = Self::new(lifts, clock);
+    }
+
+    // 电梯的使用统计已经累计到了 clock 时刻
+    pub fn advance(&mut self, clock: f64) {
+        self.last = self.last.max(clock);
+    }
+
+    // 移除了第 no 部电梯，以后新增的电梯从零开始统计
+    pub fn remove_lift(&mut self, no: usize) {
+        self.baseline.truncate(no);
+    }
+
+    // 统计开始到 clock 时刻，电梯忙碌的时间所占的比例
+    pub fn ratio(&self, lift: &Lift, clock: f64) -> f64 {
+        let total = clock - self.since;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let mut busy = lift.wear.busy - self.baseline.get(lift.no).copied().unwrap_or(0.0);
+        if lift.is_busy() {
+            busy += (clock - self.last).max(0.0);
+        }
+        (busy / total).clamp(0.0, 1.0)
+    }
+}
+
+// 界面上实时显示的指标
+#[derive(Clone, Debug, Default)]
+pub struct Dashboard {
+    // 统计开始以来运送的人数，以及他们的候梯时间
+    pub carried: usize,
+    pub waiting: Kpi,
+    // 等待调度的呼叫数、候梯的人数
+    pub calls: usize,
+    pub waiting_passengers: usize,
+    // 正在候梯的乘客里等得最久的, 单位：秒
+    pub longest_wait: f64,
+    // 每部电梯的利用率
+    pub utilisation: Vec<f64>,
+}
+
+impl Dashboard {
+    pub fn capture(sim: &Simulation, clock: f64) -> Self {
+        let waiting = sim.metrics.overall().waiting;
+        Self {
+            carried: waiting.n,
+            waiting,
+            calls: sim.wait_floors.len(),
+            waiting_passengers: sim.passengers.total_waiting(),
+            longest_wait: sim.passengers
+                .waiting_passengers()
+                .map(|p| clock - p.arrived_at)
+                .fold(0.0, f64::max),
+            utilisation: sim.lifts
+                .iter()
+                .map(|lift| sim.utilisation.ratio(lift, clock))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::conf::{ACCELERATION_IN_METERS_PER_SECOND2, JERK_IN_METERS_PER_SECOND3, RATED_SPEED_IN_METERS_PER_SECOND};
+
+// 电梯的运动参数
+// 运行曲线是加加速度受限的 S 型曲线：加速段、匀速段、减速段， 加速度的变化率不超过 jerk
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotionProfile {
+    // 额定速度, 单位：米/秒
+    pub rated_speed: f32,
+    // 最大加速度, 单位：米/秒²
+    pub acceleration: f32,
+    // 最大加加速度, 单位：米/秒³
+    pub jerk: f32,
+}
+
+impl Default for MotionProfile {
+    fn default() -> Self {
+        Self {
+            rated_speed: RATED_SPEED_IN_METERS_PER_SECOND,
+            acceleration: ACCELERATION_IN_METERS_PER_SECOND2,
+            jerk: JERK_IN_METERS_PER_SECOND3,
+        }
+    }
+}
+
+// 加加速度恒定的一段运动
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    duration: f32,
+    jerk: f32,
+}
+
+impl MotionProfile {
+    // 从静止出发、运行 distance 米后停下所需的时间, 单位：秒
+    pub fn travel_time(&self, distance: f32) -> f32 {
+        self.segments(distance)
+            .iter()
+            .map(|s| s.duration)
+            .sum()
+    }
+
+    // 一趟 distance 米的行程里，出发 t 秒后走过的距离, 单位：米
+    pub fn distance_at(&self, distance: f32, t: f32) -> f32 {
+        if distance <= 0.0 || t <= 0.0 {
+            return 0.0;
+        }
+        let (mut x, mut v, mut a) = (0.0f32, 0.0f32, 0.0f32);
+        let mut left = t;
+        for seg in self.segments(distance) {
+            let dt = seg.duration.min(left);
+            x += v * dt + a * dt * dt / 2.0 + seg.jerk * dt * dt * dt / 6.0;
+            v += a * dt + seg.jerk * dt * dt / 2.0;
+            a += seg.jerk * dt;
+            left -= dt;
+            if left <= 0.0 {
+                break;
+            }
+        }
+        x.clamp(0.0, distance)
+    }
+
+    // 一趟 distance 米的行程里，走到 d 米处所需的时间, 单位：秒
+    pub fn time_at(&self, distance: f32, d: f32) -> f32 {
+        let total = self.travel_time(distance);
+        if d <= 0.0 {
+            return 0.0;
+        }
+        if d >= distance {
+            return total;
+        }
+        // 走过的距离随时间单调递增，用二分法反推时间
+        let (mut lo, mut hi) = (0.0f32, total);
+        for _ in 0..40 {
+            let mid = (lo + hi) / 2.0;
+            if self.distance_at(distance, mid) < d {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        hi
+    }
+
+    // 行程能达到的最高速度，以及这时的最大加速度
+    fn peak(&self, distance: f32) -> (f32, f32) {
+        let (v, a, j) = (self.rated_speed, self.acceleration, self.jerk);
+        // 能加速到额定速度， 并且有足够的距离减速
+        let full_acc = if v >= a * a / j { v * (v / a + a / j) } else { 2.0 * v * (v / j).sqrt() };
+        if distance >= full_acc {
+            return if v >= a * a / j { (v, a) } else { (v, (v * j).sqrt()) };
+        }
+        // 达不到额定速度，但能达到最大加速度
+        let peak = (-a / j + (a * a / (j * j) + 4.0 * distance / a).sqrt()) * a / 2.0;
+        if peak >= a * a / j {
+            return (peak, a);
+        }
+        // 连最大加速度都达不到
+        let peak = (distance * j.sqrt() / 2.0).powf(2.0 / 3.0);
+        (peak, (peak * j).sqrt())
+    }
+
+    fn segments(&self, distance: f32) -> Vec<Segment> {
+        if distance <= 0.0 {
+            return vec![];
+        }
+        let (v, a) = self.peak(distance);
+        let j = self.jerk;
+        let ramp = a / j;
+        let hold = (v / a - ramp).max(0.0);
+        // 加速和减速一共走过的距离
+        let acc_distance = v * (2.0 * ramp + hold);
+        let cruise = ((distance - acc_distance) / v).max(0.0);
+        vec![
+            Segment { duration: ramp, jerk: j },
+            Segment { duration: hold, jerk: 0.0 },
+            Segment { duration: ramp, jerk: -j },
+            Segment { duration: cruise, jerk: 0.0 },
+            Segment { duration: ramp, jerk: -j },
+            Segment { duration: hold, jerk: 0.0 },
+            Segment { duration: ramp, jerk: j },
+        ]
+    }
+}
+
+// 电梯的一趟行程，从静止出发，到静止停下
+// 电梯仍然一层一层地运行，每层楼的运行时间按行程的运行曲线计算
+#[derive(Clone, Copy, Debug)]
+pub struct Trip {
+    // 出发时的高度, 单位：米
+    pub origin: f32,
+    // 目标高度, 单位：米
+    pub target: f32,
+    // 正在运行的这一层楼，在行程里开始、结束的时刻, 单位：秒
+    pub leg_start: f32,
+    pub leg_end: f32,
+    // 正在运行的这一层楼实际开始的时刻
+    pub leg_started_at: Instant,
+}
+
+impl Trip {
+    pub fn new(origin: f32) -> Self {
+        Self {
+            origin,
+            target: origin,
+            leg_start: 0.0,
+            leg_end: 0.0,
+            leg_started_at: Instant::now(),
+        }
+    }
+
+    pub fn distance(&self) -> f32 {
+        (self.target - self.origin).abs()
+    }
+
+    // 开始运行行程里的一段, 从 from 高度运行到 to 高度，返回这一段的运行时间, 单位：秒
+    pub fn begin_leg(&mut self, profile: &MotionProfile, from: f32, to: f32, started_at: Instant) -> f32 {
+        let distance = self.distance();
+        self.leg_start = profile.time_at(distance, (from - self.origin).abs());
+        self.leg_end = profile.time_at(distance, (to - self.origin).abs());
+        self.leg_started_at = started_at;
+        self.leg_end - self.leg_start
+    }
+
+    // 某一时刻电梯所在的高度, 单位：米
+    pub fn position(&self, profile: &MotionProfile, now: Instant) -> f32 {
+        let elapsed = now
+            .checked_duration_since(self.leg_started_at)
+            .map(|d| d.as_secs_f32())
+            .unwrap_or(0.0);
+        // 到达消息可能晚一点才处理，不能越过这一段的终点
+        let t = (self.leg_start + elapsed).min(self.leg_end);
+        let d = profile.distance_at(self.distance(), t);
+        if self.target >= self.origin {
+            self.origin + d
+        } else {
+            self.origin - d
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+    }
+
+    // 额定速度 2 米/秒，最大加速度 1 米/秒²，最大加加速度 1 米/秒³
+    // 加速到额定速度要 3 秒，走 3 米，所以 6 米以上的行程能达到额定速度
+    fn profile() -> MotionProfile {
+        MotionProfile {
+            rated_speed: 2.0,
+            acceleration: 1.0,
+            jerk: 1.0,
+        }
+    }
+
+    #[test]
+    fn reaches_rated_speed() {
+        let p = profile();
+        assert_eq!(p.peak(10.0), (2.0, 1.0));
+        // 加速 3 秒，匀速 2 秒，减速 3 秒
+        assert_close(p.travel_time(10.0), 8.0);
+        assert_close(p.travel_time(6.0), 6.0);
+    }
+
+    #[test]
+    fn reaches_acceleration_but_not_rated_speed() {
+        let p = profile();
+        let (v, a) = p.peak(3.75);
+        assert_close(v, 1.5);
+        assert_close(a, 1.0);
+        // 加速、减速各 2.5 秒，没有匀速段
+        assert_close(p.travel_time(3.75), 5.0);
+    }
+
+    #[test]
+    fn reaches_neither_acceleration_nor_rated_speed() {
+        let p = profile();
+        let (v, a) = p.peak(0.25);
+        assert_close(v, 0.25);
+        assert_close(a, 0.5);
+        assert_close(p.travel_time(0.25), 2.0);
+    }
+
+    #[test]
+    fn low_rated_speed_never_reaches_acceleration() {
+        // 额定速度低于 a²/j 时，加速度还没到最大就到额定速度了
+        let p = MotionProfile {
+            rated_speed: 0.5,
+            ..profile()
+        };
+        let (v, a) = p.peak(2.0);
+        assert_close(v, 0.5);
+        assert_close(a, 0.5f32.sqrt());
+        assert_close(p.travel_time(2.0), 5.414_214);
+    }
+
+    #[test]
+    fn distance_and_time_are_consistent() {
+        let p = profile();
+        assert_eq!(p.travel_time(0.0), 0.0);
+        assert_eq!(p.distance_at(10.0, 0.0), 0.0);
+        // 运行曲线前后对称，走到一半正好用一半的时间
+        assert_close(p.distance_at(10.0, 4.0), 5.0);
+        assert_close(p.distance_at(10.0, 8.0), 10.0);
+        assert_close(p.distance_at(10.0, 100.0), 10.0);
+        assert_close(p.time_at(10.0, 5.0), 4.0);
+        assert_close(p.time_at(10.0, 10.0), 8.0);
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use crate::building::building;
+use crate::conf::{ALIGHTING_TIME_IN_SECONDS, BALK_LOAD_FACTOR, BOARDING_TIME_IN_SECONDS, PASSENGER_WEIGHT_IN_KG, PASSENGER_WEIGHT_RANGE_IN_KG, STAIRS_MAX_FLOORS, STAIRS_PATIENCE_IN_SECONDS, TFloor, WALKING_TIME_IN_SECONDS};
+use crate::floor_btn::Direction;
+
+// 乘客体重的分布：截断的正态分布, 单位：千克
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WeightDistribution {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for WeightDistribution {
+    fn default() -> Self {
+        Self {
+            mean: PASSENGER_WEIGHT_IN_KG.0,
+            std_dev: PASSENGER_WEIGHT_IN_KG.1,
+            min: PASSENGER_WEIGHT_RANGE_IN_KG.0,
+            max: PASSENGER_WEIGHT_RANGE_IN_KG.1,
+        }
+    }
+}
+
+impl WeightDistribution {
+    // 随机抽取一位乘客的体重
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        match Normal::new(self.mean, self.std_dev) {
+            Ok(normal) => normal.sample(rng).max(self.min).min(self.max),
+            // 标准差不合法时，所有人都是平均体重
+            Err(_) => self.mean,
+        }
+    }
+}
+
+// 乘客的行为参数
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Behaviour {
+    // 电梯开门后，乘客从候梯的地方走到轿厢门口的时间范围, 单位：秒
+    pub walking: (f64, f64),
+    // 乘客的体重分布
+    pub weight: WeightDistribution,
+    // 每人进入轿厢的时间, 单位：秒
+    pub boarding: f64,
+    // 每人走出轿厢的时间, 单位：秒
+    pub alighting: f64,
+    // 轿厢载荷率达到这个值时，候梯的人就不进去了，等下一部电梯
+    pub balk_load_factor: f64,
+    // 只走几层的乘客，等电梯超过这个时间就改走楼梯, 单位：秒； 为 None 时都不走楼梯
+    pub stairs_patience: Option<f64>,
+    // 最多走几层楼梯
+    pub stairs_max_floors: u32,
+}
+
+impl Default for Behaviour {
+    fn default() -> Self {
+        Self {
+            walking: WALKING_TIME_IN_SECONDS,
+            weight: Default::default(),
+            boarding: BOARDING_TIME_IN_SECONDS,
+            alighting: ALIGHTING_TIME_IN_SECONDS,
+            balk_load_factor: BALK_LOAD_FACTOR,
+            stairs_patience: Some(STAIRS_PATIENCE_IN_SECONDS),
+            stairs_max_floors: STAIRS_MAX_FLOORS,
+        }
+    }
+}
+
+// 乘客
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Passenger {
+    // 乘客编号
+    pub id: usize,
+    // 出发楼层
+    pub origin: TFloor,
+    // 目的楼层
+    pub destination: TFloor,
+    // 到达候梯厅的时刻, 单位：秒
+    pub arrived_at: f64,
+    // 从候梯的地方走到轿厢门口的时间, 单位：秒
+    pub walking: f64,
+    // 体重（含随身物品）, 单位：千克
+    pub weight: f64,
+    // 因为轿厢太挤没有进去的次数
+    pub balked: u32,
+    // 等不及改走楼梯了
+    pub took_stairs: bool,
+    // 进入电梯的时刻
+    pub boarded_at: Option<f64>,
+    // 接他的电梯到站的时刻
+    pub picked_up_at: Option<f64>,
+    // 离开电梯的时刻
+    pub alighted_at: Option<f64>,
+    // 乘坐的电梯序号
+    pub lift: Option<usize>,
+}
+
+impl Passenger {
+    pub fn direction(&self) -> Direction {
+        if self.destination > self.origin {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+
+    // 要走过的楼层数
+    pub fn floors(&self) -> u32 {
+        building().distance(self.origin, self.destination)
+    }
+}
+
+// 各楼层候梯的乘客，以及已经到达目的楼层的乘客
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PassengerQueues {
+    next_id: usize,
+    // 每个楼层按到达顺序排队的乘客
+    waiting: BTreeMap<TFloor, VecDeque<Passenger>>,
+    // 已经到达目的楼层的乘客
+    pub finished: Vec<Passenger>,
+}
+
+impl PassengerQueues {
+    // 新乘客到达候梯厅，返回乘客编号
+    pub fn arrive(&mut self, origin: TFloor, destination: TFloor, now: f64, walking: f64, weight: f64) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiting
+            .entry(origin)
+            .or_default()
+            .push_back(Passenger {
+                id,
+                origin,
+                destination,
+                arrived_at: now,
+                walking,
+                weight,
+                balked: 0,
+                took_stairs: false,
+                boarded_at: None,
+                picked_up_at: None,
+                alighted_at: None,
+                lift: None,
+            });
+        id
+    }
+
+    // 某楼层某方向上候梯的人数
+    pub fn waiting_count(&self, floor: TFloor, direction: Direction) -> usize {
+        self.waiting
+            .get(&floor)
+            .map(|q| q.iter().filter(|p| p.direction() == direction).count())
+            .unwrap_or(0)
+    }
+
+    // floor 楼层去 direction 方向的乘客要去的楼层
+    pub fn destinations(&self, floor: TFloor, direction: Direction) -> impl Iterator<Item=TFloor> + '_ {
+        self.waiting
+            .get(&floor)
+            .into_iter()
+            .flat_map(|q| q.iter())
+            .filter(move |p| p.direction() == direction)
+            .map(|p| p.destination)
+    }
+
+    // 所有候梯的人数
+    pub fn total_waiting(&self) -> usize {
+        self.waiting.values().map(|q| q.len()).sum()
+    }
+
+    // 所有候梯的乘客
+    pub fn waiting_passengers(&self) -> impl Iterator<Item=&Passenger> {
+        self.waiting.values().flat_map(|q| q.iter())
+    }
+
+    // 按排队顺序，最多让 capacity 个去 direction 方向的乘客进入 lift 号电梯，电梯不停靠目的楼层的乘客不进去
+    // start 是开始进人的时刻，乘客同时走向轿厢，再一个接一个地进入
+    pub fn board(&mut self, floor: TFloor, direction: Direction, capacity: usize, lift: usize, serves: &BTreeSet<TFloor>, start: f64, behaviour: &Behaviour) -> Vec<Passenger> {
+        let queue = match self.waiting.get_mut(&floor) {
+            Some(queue) => queue,
+            None => return vec![],
+        };
+        let mut boarded = vec![];
+        let mut left = VecDeque::with_capacity(queue.len());
+        while let Some(mut p) = queue.pop_front() {
+            if boarded.len() < capacity && p.direction() == direction && serves.contains(&p.destination) {
+                p.lift = Some(lift);
+                boarded.push(p);
+            } else {
+                left.push_back(p);
+            }
+        }
+        *queue = left;
+        let mut at = start;
+        for p in boarded.iter_mut() {
+            // 没走到门口的要等他走过来
+            at = at.max(start + p.walking) + behaviour.boarding;
+            p.boarded_at = Some(at);
+        }
+        boarded
+    }
+
+    // 超载时最后进去的乘客退出电梯，排回队伍的最前面
+    pub fn step_out(&mut self, mut passenger: Passenger) {
+        passenger.boarded_at = None;
+        passenger.picked_up_at = None;
+        passenger.lift = None;
+        self.waiting
+            .entry(passenger.origin)
+            .or_default()
+            .push_front(passenger);
+    }
+
+    // 轿厢太挤，floor 楼层去 direction 方向的乘客都不进去，返回没进去的人数
+    pub fn balk(&mut self, floor: TFloor, direction: Direction) -> usize {
+        self.waiting
+            .get_mut(&floor)
+            .map(|q| q
+                .iter_mut()
+                .filter(|p| p.direction() == direction)
+                .map(|p| p.balked += 1)
+                .count())
+            .unwrap_or(0)
+    }
+
+    // 乘客在 start 时刻开始一个接一个地走出电梯
+    pub fn alight(&mut self, mut passengers: Vec<Passenger>, start: f64, behaviour: &Behaviour) {
+        let mut at = start;
+        for p in passengers.iter_mut() {
+            at += behaviour.alighting;
+            p.alighted_at = Some(at);
+        }
+        self.finished.append(&mut passengers);
+    }
+
+    // 只走几层的乘客等得太久，就改走楼梯，返回走楼梯的乘客所在的楼层和方向
+    pub fn take_stairs(&mut self, now: f64, behaviour: &Behaviour) -> Vec<(TFloor, Direction)> {
+        let patience = match behaviour.stairs_patience {
+            Some(patience) => patience,
+            None => return vec![],
+        };
+        let mut ret = vec![];
+        for queue in self.waiting.values_mut() {
+            let mut left = VecDeque::with_capacity(queue.len());
+            while let Some(mut p) = queue.pop_front() {
+                if now - p.arrived_at >= patience && p.floors() <= behaviour.stairs_max_floors {
+                    ret.push((p.origin, p.direction()));
+                    p.took_stairs = true;
+                    self.finished.push(p);
+                } else {
+                    left.push_back(p);
+                }
+            }
+            *queue = left;
+        }
+        ret
+    }
+}
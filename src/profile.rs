@@ -0,0 +1,148 @@
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use crate::config::config;
+use crate::conf::TFloor;
+use crate::passenger::Behaviour;
+use crate::traffic::{ArrivalProcess, OdMatrix, TrafficGenerator};
+use crate::util::all_floors;
+
+// 内置的标准客流
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum TrafficProfile {
+    // 早高峰：大厅上行
+    UpPeak,
+    // 晚高峰：下行到大厅
+    DownPeak,
+    // 午间：上下行都有
+    Lunch,
+    // 层间均匀出行
+    Interfloor,
+    // 按配置里 traffic.od 的 OD 矩阵
+    Custom,
+}
+
+impl Default for TrafficProfile {
+    fn default() -> Self {
+        TrafficProfile::Interfloor
+    }
+}
+
+impl Display for TrafficProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        use TrafficProfile::*;
+        write!(f, "{}", match self {
+            UpPeak => "早高峰",
+            DownPeak => "晚高峰",
+            Lunch => "午间",
+            Interfloor => "层间",
+            Custom => "自定义",
+        })
+    }
+}
+
+// 午间客流里，上行、下行以外的层间出行的比例
+const LUNCH_INTERFLOOR_SHARE: f64 = 0.1;
+
+impl TrafficProfile {
+    pub const ALL: [TrafficProfile; 5] = [
+        TrafficProfile::UpPeak,
+        TrafficProfile::DownPeak,
+        TrafficProfile::Lunch,
+        TrafficProfile::Interfloor,
+        TrafficProfile::Custom,
+    ];
+
+    // 命令行里使用的名称
+    pub fn name(&self) -> &'static str {
+        use TrafficProfile::*;
+        match self {
+            UpPeak => "up-peak",
+            DownPeak => "down-peak",
+            Lunch => "lunch",
+            Interfloor => "interfloor",
+            Custom => "custom",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|p| p.name() == name)
+            .copied()
+    }
+
+    // 每分钟到达的人数，intensity 是每 5 分钟到达的人数占大楼总人数的百分比
+    pub fn rate_per_minute(intensity: f64) -> f64 {
+        config().traffic.population as f64 * intensity / 100.0 / 5.0
+    }
+
+    // 按强度 intensity 和配置里 traffic.rates 的倍数得到的到达过程
+    pub fn arrival_process(intensity: f64) -> ArrivalProcess {
+        let rate = Self::rate_per_minute(intensity);
+        let traffic = &config().traffic;
+        if traffic.rates.is_empty() {
+            return ArrivalProcess::constant(rate);
+        }
+        ArrivalProcess {
+            rates: traffic.rates
+                .iter()
+                .map(|(start, factor)| (*start, rate * factor))
+                .collect(),
+            period: traffic.period,
+        }
+    }
+
+    // 这种客流的 OD 矩阵
+    pub fn od_matrix(&self, floors: &[TFloor], lobby: TFloor) -> OdMatrix {
+        let n = floors.len();
+        let lobby_idx = floors
+            .iter()
+            .position(|f| *f == lobby)
+            .unwrap_or(0);
+        // 从大厅上行去大厅以上的楼层、从大厅以上的楼层下行回大厅
+        // 去地下室的是下行，从地下室来的是上行，都不算在早晚高峰里
+        let mut up = vec![vec![0.0; n]; n];
+        let mut down = vec![vec![0.0; n]; n];
+        for i in lobby_idx + 1..n {
+            up[lobby_idx][i] = 1.0;
+            down[i][lobby_idx] = 1.0;
+        }
+        let weights = match self {
+            TrafficProfile::UpPeak => up,
+            TrafficProfile::DownPeak => down,
+            TrafficProfile::Lunch => {
+                // 上行、下行各占一半，剩下的是均匀的层间出行
+                let sum = (n - 1 - lobby_idx).max(1) as f64;
+                let interfloor = LUNCH_INTERFLOOR_SHARE / (n * (n - 1)) as f64;
+                (0..n).map(|i| (0..n).map(|j| {
+                    if i == j {
+                        0.0
+                    } else {
+                        (up[i][j] + down[i][j]) * (1.0 - LUNCH_INTERFLOOR_SHARE) / (2.0 * sum) + interfloor
+                    }
+                }).collect()).collect()
+            }
+            TrafficProfile::Interfloor => return OdMatrix::uniform(floors.to_vec()),
+            TrafficProfile::Custom => {
+                // 配置校验过 traffic.od 和楼层对得上
+                let od = &config().traffic.od;
+                if od.is_empty() {
+                    return OdMatrix::uniform(floors.to_vec());
+                }
+                od.clone()
+            }
+        };
+        OdMatrix::new(floors.to_vec(), weights).unwrap()
+    }
+
+    // 按强度 intensity 产生这种客流的生成器，乘客走到轿厢门口的时间和体重分布按 behaviour
+    pub fn generator(&self, intensity: f64, seed: u64, behaviour: &Behaviour) -> TrafficGenerator {
+        TrafficGenerator::new(
+            Self::arrival_process(intensity),
+            self.od_matrix(&all_floors(), config().building.lobby),
+            seed,
+            behaviour.walking,
+            behaviour.weight,
+        )
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::BTreeSet;
+use crate::batch::Replication;
+use crate::building::building;
+use crate::conf::TFloor;
+use crate::config::{Config, LiftSpec};
+
+// 早高峰的经典往返时间计算，只考虑从大厅上行到大厅以上的楼层
+// 假设各楼层人数相同，每趟载客为额定人数的 80%，所有电梯都用统一的参数
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UpPeak {
+    // 电梯数量
+    pub lifts: usize,
+    // 大厅以上的楼层数
+    pub floors: usize,
+    // 每趟的载客人数
+    pub passengers: f64,
+    // 预期的停站次数
+    pub stops: f64,
+    // 最高折返层，大厅往上数第几层
+    pub reversal: f64,
+    // 额定速度下走一层的时间, 单位：秒
+    pub floor_time: f64,
+    // 每次停站损失的时间：加减速多用的时间，加上开门、停留、关门的时间, 单位：秒
+    pub stop_time: f64,
+    // 每位乘客进出轿厢的时间, 单位：秒
+    pub transfer_time: f64,
+    // 往返时间、发车间隔, 单位：秒
+    pub rtt: f64,
+    pub interval: f64,
+    // 5 分钟运送的人数，以及占大楼总人数的百分比
+    pub handling_capacity: f64,
+    pub percent_population: f64,
+}
+
+// 经典算法里每趟的载客率
+const LOAD_RATIO: f64 = 0.8;
+
+impl UpPeak {
+    // 前 lifts 部电梯在 lift_specs 里单独设置了不一样的载客人数、速度、开关门时间或者停靠楼层时，不能用这个算法
+    pub fn calculate(config: &Config, lifts: usize) -> Result<Self, String> {
+        for (no, spec) in config.lift_specs.iter().enumerate().take(lifts) {
+            let differs = Self::differs(config, spec);
+            if !differs.is_empty() {
+                return Err(format!("电梯#{}在 lift_specs 里单独设置的{}和统一的参数不一样，经典的早高峰算法假设所有电梯都一样，不能计算",
+                                   no + 1, differs.join("、")));
+            }
+        }
+        let building = config.to_building()?;
+        let lobby = building
+            .get(config.building.lobby)
+            .ok_or_else(|| format!("大厅{}层不在大楼里", config.building.lobby))?;
+        let upper = building
+            .floors()
+            .iter()
+            .filter(|f| f.number > lobby.number)
+            .collect::<Vec<_>>();
+        let top = upper.last().ok_or("大厅以上没有楼层，不能计算早高峰的往返时间")?;
+        let n = upper.len() as f64;
+        let p = LOAD_RATIO * config.capacity as f64;
+        // 平均层高
+        let floor_height = (top.height - lobby.height) / upper.len() as f32;
+        let floor_time = (floor_height / config.motion.rated_speed) as f64;
+        let one_floor = config.motion.travel_time(floor_height) as f64;
+        let door = config.door;
+        // 模拟里门开好后至少停留 dwell，所以也算在停站时间里
+        let stop_time = one_floor - floor_time + (door.opening + door.dwell + door.closing) as f64 / 1000.0;
+        let transfer_time = (config.passengers.boarding + config.passengers.alighting) / 2.0;
+        let stops = n * (1.0 - (1.0 - 1.0 / n).powf(p));
+        let reversal = n - (1..upper.len())
+            .map(|i| (i as f64 / n).powf(p))
+            .sum::<f64>();
+        let rtt = 2.0 * reversal * floor_time + (stops + 1.0) * stop_time + 2.0 * p * transfer_time;
+        let interval = rtt / lifts as f64;
+        let handling_capacity = 300.0 * p / interval;
+        Ok(Self {
+            lifts,
+            floors: upper.len(),
+            passengers: p,
+            stops,
+            reversal,
+            floor_time,
+            stop_time,
+            transfer_time,
+            rtt,
+            interval,
+            handling_capacity,
+            percent_population: handling_capacity * 100.0 / config.traffic.population as f64,
+        })
+    }
+
+    // 单独设置了、而且和统一的参数不一样的项
+    fn differs(config: &Config, spec: &LiftSpec) -> Vec<&'static str> {
+        let mut differs = vec![];
+        if spec.capacity.map_or(false, |c| c != config.capacity) {
+            differs.push("capacity");
+        }
+        if spec.rated_speed.map_or(false, |v| v != config.motion.rated_speed) {
+            differs.push("rated_speed");
+        }
+        if spec.door.map_or(false, |d| d != config.door) {
+            differs.push("door");
+        }
+        // 停靠了所有楼层的也和统一的一样
+        let all = config.to_building().map(|b| b.numbers()).unwrap_or_default();
+        if spec.floors.as_ref().map_or(false, |f| f.iter().collect::<BTreeSet<_>>() != all.iter().collect()) {
+            differs.push("floors");
+        }
+        differs
+    }
+
+    pub fn print(&self) {
+        println!("早高峰往返时间计算：{}部电梯，大厅以上{}层", self.lifts, self.floors);
+        println!("{:<16}{:>10.2}", "每趟载客(人)", self.passengers);
+        println!("{:<16}{:>10.2}", "预期停站(次)", self.stops);
+        println!("{:<16}{:>10.2}", "最高折返层", self.reversal);
+        println!("{:<16}{:>10.2}", "走一层(秒)", self.floor_time);
+        println!("{:<16}{:>10.2}", "每次停站(秒)", self.stop_time);
+        println!("{:<16}{:>10.2}", "每人进出(秒)", self.transfer_time);
+        println!("{:<16}{:>10.2}", "往返时间(秒)", self.rtt);
+        println!("{:<16}{:>10.2}", "发车间隔(秒)", self.interval);
+        println!("{:<16}{:>10.2}", "5分钟运送(人)", self.handling_capacity);
+        println!("{:<16}{:>10.2}", "占总人数(%)", self.percent_population);
+    }
+}
+
+// 从早高峰的模拟结果里统计出来的对应指标，只统计预热期之后
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Observed {
+    // 电梯从大厅上行出发的次数
+    pub departures: usize,
+    pub passengers: f64,
+    pub stops: f64,
+    pub reversal: f64,
+    pub rtt: f64,
+    pub interval: f64,
+    pub handling_capacity: f64,
+}
+
+impl Observed {
+    pub fn of(results: &[Replication], lobby: TFloor) -> Self {
+        let building = building();
+        let lobby_idx = building.index(lobby).unwrap_or(0);
+        let mut departures = 0;
+        let mut rtts = vec![];
+        let mut intervals = vec![];
+        // 每趟的人数、停站次数、最高折返层
+        let mut trips = vec![];
+        let mut handling = vec![];
+        for r in results {
+            // 每部电梯离开大厅上行的时刻
+            let leaving = r.positions
+                .iter()
+                .map(|floors| floors
+                    .windows(2)
+                    .filter(|w| w[0].1 == lobby && w[1].1 > lobby)
+                    .map(|w| w[1].0)
+                    .collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            let mut all = leaving
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|at| *at >= r.warmup)
+                .collect::<Vec<_>>();
+            all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            departures += all.len();
+            intervals.extend(all.windows(2).map(|w| w[1] - w[0]));
+            for times in &leaving {
+                rtts.extend(times
+                    .windows(2)
+                    .filter(|w| w[0] >= r.warmup)
+                    .map(|w| w[1] - w[0]));
+            }
+            // 把从大厅上行的乘客分到他坐的那一趟
+            let mut loads = leaving
+                .iter()
+                .map(|times| vec![vec![]; times.len()])
+                .collect::<Vec<Vec<Vec<usize>>>>();
+            let mut carried = 0;
+            for p in r.finished.iter().filter(|p| !p.took_stairs && p.origin == lobby && p.destination > lobby) {
+                if let (Some(no), Some(boarded_at)) = (p.lift, p.boarded_at) {
+                    if let Some(k) = leaving
+                        .get(no)
+                        .and_then(|times| times.iter().position(|at| *at >= boarded_at)) {
+                        if leaving[no][k] >= r.warmup {
+                            loads[no][k].push(building.index(p.destination).unwrap_or(lobby_idx));
+                            carried += 1;
+                        }
+                    }
+                }
+            }
+            for load in loads.iter().flatten().filter(|load| !load.is_empty()) {
+                let stops = load.iter().collect::<BTreeSet<_>>();
+                trips.push((load.len() as f64, stops.len() as f64,
+                            (*stops.iter().last().unwrap() - lobby_idx) as f64));
+            }
+            handling.push(carried as f64 * 300.0 / (r.duration - r.warmup));
+        }
+        let mean = |v: &[f64]| if v.is_empty() { f64::NAN } else { v.iter().sum::<f64>() / v.len() as f64 };
+        Self {
+            departures,
+            passengers: mean(&trips.iter().map(|t| t.0).collect::<Vec<_>>()),
+            stops: mean(&trips.iter().map(|t| t.1).collect::<Vec<_>>()),
+            reversal: mean(&trips.iter().map(|t| t.2).collect::<Vec<_>>()),
+            rtt: mean(&rtts),
+            interval: mean(&intervals),
+            handling_capacity: mean(&handling),
+        }
+    }
+}
+
+// 计算值和模拟值对照
+pub fn print_comparison(calc: &UpPeak, observed: &Observed) {
+    println!("{:<16}{:>10}{:>10}{:>10}", "指标", "计算", "模拟", "偏差(%)");
+    let rows = [
+        ("每趟载客(人)", calc.passengers, observed.passengers),
+        ("预期停站(次)", calc.stops, observed.stops),
+        ("最高折返层", calc.reversal, observed.reversal),
+        ("往返时间(秒)", calc.rtt, observed.rtt),
+        ("发车间隔(秒)", calc.interval, observed.interval),
+        ("5分钟运送(人)", calc.handling_capacity, observed.handling_capacity),
+    ];
+    for (name, calculated, simulated) in rows {
+        println!("{:<16}{:>10.2}{:>10.2}{:>10.1}", name, calculated, simulated,
+                 (simulated - calculated) * 100.0 / calculated);
+    }
+    println!("共统计{}次从大厅上行出发", observed.departures);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 大厅在 1 层，上面 10 层，每层 3.3 米，每部电梯 20 人，每趟载客 16 人
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.lifts = 4;
+        config.capacity = 20;
+        config.building.lowest = 1;
+        config.building.highest = 11;
+        config.building.floor_height = 3.3;
+        config.motion.rated_speed = 1.6;
+        config.traffic.population = 1000;
+        config
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 0.005, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn stops_and_reversal_floor() {
+        let calc = UpPeak::calculate(&config(), 4).unwrap();
+        assert_eq!(calc.floors, 10);
+        assert_close(calc.passengers, 16.0);
+        // S = N(1-(1-1/N)^P)，H = N-Σ(i/N)^P，按公式手算的结果
+        assert_close(calc.stops, 8.147);
+        assert_close(calc.reversal, 9.783);
+    }
+
+    #[test]
+    fn round_trip_time_interval_and_capacity() {
+        let config = config();
+        let calc = UpPeak::calculate(&config, 4).unwrap();
+        // 走一层 3.3/1.6 秒，每人进出 (1.2+1.0)/2 秒
+        assert_close(calc.floor_time, 2.0625);
+        assert_close(calc.transfer_time, 1.1);
+        let door = config.door;
+        assert_close(calc.stop_time, config.motion.travel_time(3.3) as f64 - 2.0625
+            + (door.opening + door.dwell + door.closing) as f64 / 1000.0);
+        // RTT = 2Htv + (S+1)ts + 2Ptp
+        let rtt = 2.0 * 9.7829 * 2.0625 + (8.1470 + 1.0) * calc.stop_time + 2.0 * 16.0 * 1.1;
+        assert!((calc.rtt - rtt).abs() < 0.01, "{} != {}", calc.rtt, rtt);
+        assert_close(calc.interval, calc.rtt / 4.0);
+        assert_close(calc.handling_capacity, 300.0 * 16.0 / calc.interval);
+        assert_close(calc.percent_population, calc.handling_capacity / 10.0);
+        // 电梯多一倍，间隔减半，运送能力加倍
+        let double = UpPeak::calculate(&config, 8).unwrap();
+        assert_close(double.interval, calc.interval / 2.0);
+        assert_close(double.handling_capacity, calc.handling_capacity * 2.0);
+    }
+
+    #[test]
+    fn rejects_different_lift_specs() {
+        let mut config = config();
+        config.lift_specs = vec![
+            LiftSpec {
+                capacity: Some(20),
+                floors: Some((1..=11).collect()),
+                ..Default::default()
+            },
+            LiftSpec {
+                capacity: Some(10),
+                rated_speed: Some(4.0),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(UpPeak::calculate(&config, 4).unwrap_err(),
+                   "电梯#2在 lift_specs 里单独设置的capacity、rated_speed和统一的参数不一样，经典的早高峰算法假设所有电梯都一样，不能计算");
+        // 不参与计算的电梯可以不一样
+        assert!(UpPeak::calculate(&config, 1).is_ok());
+    }
+}
@@ -0,0 +1,850 @@
+use std::collections::LinkedList;
+use std::time::{Duration, Instant};
+use crate::building::building;
+use crate::config::{config, set_config};
+use crate::conf::{STAIRS_PATIENCE_IN_SECONDS, TFloor};
+use crate::dispatch::Dispatch;
+use crate::door::DoorState;
+use crate::floor_btn::{Direction, WaitFloorTxtState};
+use crate::lift::Lift;
+use crate::metrics::{Metrics, Utilisation};
+use crate::passenger::{Behaviour, PassengerQueues};
+use crate::profile::TrafficProfile;
+use crate::state::State;
+use crate::trace::TraceInput;
+use crate::traffic::{Arrival, TrafficGenerator};
+use crate::up_down_elevator_floor::*;
+use crate::util::next_floor;
+
+// 运行日志，批量模拟时关掉
+macro_rules! log {
+    ($sim:expr, $($arg:tt)*) => {
+        if $sim.verbose {
+            println!($($arg)*);
+        }
+    };
+}
+
+// 模拟需要外面安排的定时器，到期后调用对应的处理函数
+// 界面里交给电梯任务，批量模拟时放进事件队列
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timer {
+    // 电梯序号，运行这层楼需要的时间, 到期后调用 arrive_by_one_floor
+    RunOneFloor(usize, u32),
+    // 电梯序号，定时时间，门的状态序号, 到期后调用 on_door_timer
+    Door(usize, u32, u32),
+}
+
+// 电梯群的模拟：电梯、候梯的乘客、呼叫和调度
+// 模拟本身不计时，时间由外面推进：界面里跟着真实时间走，批量模拟时按事件跳着走
+pub struct Simulation {
+    pub lifts: Vec<Lift>,
+    // 哪些楼层需要安排电梯去接人的
+    pub wait_floors: LinkedList<WaitFloorTxtState>,
+    // 各楼层候梯的乘客
+    pub passengers: PassengerQueues,
+    // 乘客的行为参数
+    pub behaviour: Behaviour,
+    // 每位乘完电梯的乘客的候梯、乘梯时间
+    pub metrics: Metrics,
+    // 每部电梯忙碌的时间
+    pub utilisation: Utilisation,
+    // 调度策略
+    pub dispatch: Dispatch,
+    // 自动客流生成器，以及它启动的时刻
+    pub traffic: Option<(TrafficGenerator, f64)>,
+    // 模拟时钟, 单位：秒
+    pub clock: f64,
+    // 模拟时钟为 epoch_clock 时对应的 Instant，门和行程的计时都用这个换算
+    epoch: Instant,
+    epoch_clock: f64,
+    // 是否输出运行日志
+    pub verbose: bool,
+    // 还没交给外面的定时器
+    timers: Vec<Timer>,
+}
+
+impl Simulation {
+    pub fn new(clock: f64) -> Self {
+        Self {
+            lifts: vec![],
+            wait_floors: Default::default(),
+            passengers: Default::default(),
+            behaviour: config().passengers,
+            metrics: Default::default(),
+            utilisation: Utilisation::new(&[], clock),
+            dispatch: Default::default(),
+            traffic: None,
+            clock,
+            epoch: Instant::now(),
+            epoch_clock: clock,
+            verbose: true,
+            timers: vec![],
+        }
+    }
+
+    // 模拟时钟对应的 Instant
+    pub fn instant(&self) -> Instant {
+        let d = self.clock - self.epoch_clock;
+        if d >= 0.0 {
+            self.epoch + Duration::from_secs_f64(d)
+        } else {
+            self.epoch
+                .checked_sub(Duration::from_secs_f64(-d))
+                .unwrap_or(self.epoch)
+        }
+    }
+
+    // 恢复快照时，把还没到期的定时器重新交给外面
+    pub fn push_timer(&mut self, timer: Timer) {
+        self.timers.push(timer);
+    }
+
+    // 取出还没交给外面的定时器
+    pub fn take_timers(&mut self) -> Vec<Timer> {
+        std::mem::take(&mut self.timers)
+    }
+
+    // 新增一部电梯
+    pub fn add_lift(&mut self) -> usize {
+        let no = self.lifts.len();
+        self.lifts.push(Lift::new(no));
+        no
+    }
+
+    // 新增一部停在 floor 层的电梯
+    pub fn add_lift_at(&mut self, floor: TFloor) -> usize {
+        let no = self.add_lift();
+        let lift = &mut self.lifts[no];
+        lift.cur_floor = floor;
+        lift.position = building().position(floor);
+        no
+    }
+
+    // 移除最后一部电梯， 它还没完成的调度马上重新分配给其他电梯
+    // 电梯里还有乘客、还有要停靠的楼层或者正在运行时不能移除，免得把乘客丢了
+    pub fn remove_lift(&mut self) -> Option<usize> {
+        let lift = self.lifts.last()?;
+        if !lift.riders.is_empty() || !lift.stop_floors.is_empty() || lift.trip.is_some() {
+            log!(self, "电梯#{}里还有乘客或者要停靠的楼层，不能移除", lift.no + 1);
+            return None;
+        }
+        let lift = self.lifts.pop()?;
+        self.utilisation.remove_lift(lift.no);
+        for floor in lift.schedule_floors.keys() {
+            self.wait_floors
+                .iter_mut()
+                .filter(|wf| wf.floor == *floor)
+                .for_each(|wf| wf.is_scheduled = false);
+        }
+        self.schedule_waiting();
+        Some(lift.no)
+    }
+
+    // 电梯状态变化之后，检查是否需要重新调度
+    // 电梯变为闲置、改变运行方向、或者结束维护时，都可能有更合适的电梯去接等待的人
+    pub fn on_lift_event<F>(&mut self, no: usize, handle: F)
+        where F: FnOnce(&mut Self, usize) {
+        let before = match self.lifts.get(no) {
+            Some(lift) => lift.state.clone(),
+            // 电梯已经被移除了
+            None => return,
+        };
+        handle(self, no);
+        let after = self.lifts[no].state.clone();
+        let became_idle = before != State::Stop && after == State::Stop;
+        let left_maintaining = before == State::Maintaining && after != State::Maintaining;
+        let direction_changed = match (before.direction(), after.direction()) {
+            (Some(b), Some(a)) => b != a,
+            _ => false,
+        };
+        if became_idle || left_maintaining {
+            // 电梯里还有没到达的楼层时，先继续运行
+            self.start_lift(no);
+        }
+        if became_idle || left_maintaining || direction_changed {
+            self.schedule_waiting();
+        }
+        if became_idle || left_maintaining {
+            self.park(no);
+        }
+    }
+
+    // 闲置的电梯回到 lift_specs 里设置的待命楼层
+    fn park(&mut self, no: usize) {
+        let parking = match config().lift_specs.get(no).and_then(|spec| spec.parking) {
+            Some(parking) => parking,
+            None => return,
+        };
+        let lift = &mut self.lifts[no];
+        if lift.state != State::Stop || lift.cur_floor == parking || !lift.serves(parking)
+            || !lift.stop_floors.is_empty() || !lift.schedule_floors.is_empty() {
+            return;
+        }
+        lift.stop_floors.insert(parking, None);
+        log!(self, "电梯#{}闲置，回到{}层待命", no + 1, parking);
+        self.start_lift(no);
+    }
+
+    // 启动静止的电梯，第一个楼层决定电梯的运行方向
+    pub fn start_lift(&mut self, no: usize) {
+        let lift = match self.lifts.get_mut(no) {
+            // 门没关好不能出发
+            Some(lift) if lift.state == State::Stop && lift.door.is_closed() => lift,
+            _ => return,
+        };
+        if let Some(first) = lift.dest_floor() {
+            if first > lift.cur_floor {
+                lift.state = State::GoingUp
+            } else if first < lift.cur_floor {
+                lift.state = State::GoingDown
+            } else {
+                lift.state = State::GoingUpSuspend
+            }
+            lift.set_lift_btn_click();
+            log!(self, "start_lift {},{}", lift.to_string(),
+                 lift.stop_floors.keys().map(|k| k.to_string())
+                     .collect::<Vec<_>>().join(","));
+            self.run_lift(no);
+        }
+    }
+
+    // 让电梯运行一层楼
+    fn run_lift(&mut self, no: usize) {
+        let now = self.instant();
+        let millis = self.lifts[no].begin_leg(now);
+        self.timers.push(Timer::RunOneFloor(no, millis));
+    }
+
+    // 调度所有还没有安排电梯的等待楼层
+    pub fn schedule_waiting(&mut self) {
+        // 电梯的调度楼层每层只记一个方向，同一层两个方向的呼叫都派给同一部电梯时，前一个会被覆盖
+        // 这样的呼叫没有电梯会去接，要重新调度
+        let lifts = &self.lifts;
+        self.wait_floors
+            .iter_mut()
+            .filter(|wf| wf.is_scheduled && !lifts
+                .iter()
+                .any(|lift| lift.schedule_floors.get(&wf.floor) == Some(&Some(wf.direction))))
+            .for_each(|wf| wf.is_scheduled = false);
+        let waiting = self.wait_floors
+            .iter()
+            .filter(|wf| !wf.is_scheduled)
+            .map(|wf| (wf.floor, wf.direction))
+            .collect::<Vec<_>>();
+        for (floor, direction) in waiting {
+            self.schedule(floor, direction);
+        }
+    }
+
+    // 切换电梯的维护状态，只有静止并且关好门的电梯才能进入维护
+    pub fn toggle_maintaining(&mut self, no: usize) {
+        let lift = &mut self.lifts[no];
+        match lift.state {
+            State::Maintaining => lift.state = State::Stop,
+            State::Stop if lift.stop_floors.is_empty() && lift.schedule_floors.is_empty() && lift.door.is_closed() => {
+                lift.state = State::Maintaining
+            }
+            _ => {}
+        }
+        lift.set_lift_btn_click();
+    }
+
+    // 电梯运行了一层楼
+    pub fn arrive_by_one_floor(&mut self, no: usize) {
+        let lift = match self.lifts.get_mut(no) {
+            Some(lift) => lift,
+            // 电梯已经被移除了
+            None => return,
+        };
+        let no = lift.no;
+        if let Some(dest_floor) = lift.dest_floor() {
+            lift.set_lift_btn_click();
+            log!(self, "ArriveByOneFloor_Some, {}, {}", lift.to_string(), dest_floor);
+            if lift.state == State::Stop{
+                if lift.cur_floor < dest_floor{
+                    lift.state = State::GoingUp;
+                }else if lift.cur_floor > dest_floor{
+                    lift.state = State::GoingDown;
+                }
+            }
+            // 按楼层的索引走到相邻的楼层
+            let next = match lift.state {
+                State::GoingUp => next_floor(lift.cur_floor, Direction::Up),
+                State::GoingDown => next_floor(lift.cur_floor, Direction::Down),
+                _ => None,
+            };
+            if let Some(next) = next {
+                lift.arrive_floor(next, false);
+            }
+            let is_arrive = lift.cur_floor == dest_floor;
+            if is_arrive {
+                // 一趟行程结束
+                lift.arrive_floor(dest_floor, true);
+                lift.state = match lift.state {
+                    State::GoingUp => State::GoingUpSuspend,
+                    State::GoingUpSuspend => State::GoingUp,
+                    State::GoingDown => State::GoingDownSuspend,
+                    State::GoingDownSuspend => State::GoingDown,
+                    // 只有静止、没有要停靠的楼层、门关好的电梯才能进入维护，运行中的电梯不会是维护状态
+                    // 万一是，就当作静止，停在这一层
+                    State::Stop | State::Maintaining => State::Stop,
+                };
+                return self.stop_at_floor(no, dest_floor);
+            }
+            self.run_lift(no)
+        } else {
+            log!(self, "ArriveByOneFloor_None, {}", lift.to_string());
+            lift.state = State::Stop;
+            lift.end_trip();
+            lift.set_lift_btn_click();
+        }
+    }
+
+    // 电梯停靠在楼层，开门让人进出
+    // 到达目的楼层的乘客先一个个离开，同方向候梯的乘客再走过来按顺序进入
+    // 轿厢太挤时候梯的乘客不进去，坐不下或者不进去的乘客重新呼梯
+    fn stop_at_floor(&mut self, no: usize, floor: TFloor) {
+        let behaviour = self.behaviour;
+        let instant = self.instant();
+        let lift = &mut self.lifts[no];
+        // 门开好后，乘客才开始进出
+        let open_at = self.clock + lift.door.timings.opening as f64 / 1000.0;
+        let answered = Self::remove_wait_floor(&mut self.wait_floors, floor, lift);
+        if let Some(direction) = answered {
+            self.metrics.answer(floor, direction, no, self.clock);
+        }
+        let direction = answered.or_else(|| lift.state.direction());
+        let alighted = lift.alight(floor);
+        let alighted_num = alighted.len();
+        let board_at = open_at + alighted_num as f64 * behaviour.alighting;
+        self.passengers.alight(alighted, open_at, &behaviour);
+        let finished = &self.passengers.finished;
+        finished[finished.len() - alighted_num..]
+            .iter()
+            .for_each(|p| self.metrics.record(p));
+        let mut boarded = match direction {
+            Some(direction) if lift.load_factor() >= behaviour.balk_load_factor => {
+                let balked = self.passengers.balk(floor, direction);
+                if balked > 0 {
+                    log!(self, "电梯{}太挤了，{}层有{}人不进去", no, floor, balked);
+                }
+                vec![]
+            }
+            Some(direction) => self.passengers.board(floor, direction, lift.free_capacity(), no, &lift.floors, board_at, &behaviour),
+            None => vec![],
+        };
+        boarded
+            .iter_mut()
+            .for_each(|p| p.picked_up_at = Some(self.clock));
+        let exchange_end = boarded
+            .iter()
+            .filter_map(|p| p.boarded_at)
+            .fold(board_at, f64::max);
+        let exchange = ((exchange_end - open_at) * 1000.0) as u32;
+        log!(self, "stop_at_floor {},已达到楼层{},出{}人，进{}人，需要{}豪秒。", lift.to_string(), floor, alighted_num, boarded.len(), exchange);
+        lift.board(boarded);
+        if let Some(millis) = lift.open_door(exchange, instant) {
+            self.door_timer(no, millis);
+        }
+        if let Some(direction) = direction {
+            if self.passengers.waiting_count(floor, direction) > 0 {
+                self.hall_call(floor, direction);
+            }
+        }
+    }
+
+    // 启动电梯门的定时器
+    fn door_timer(&mut self, no: usize, millis: u32) {
+        let seq = self.lifts[no].door.seq;
+        self.timers.push(Timer::Door(no, millis, seq));
+    }
+
+    // 电梯门的定时器到期
+    pub fn on_door_timer(&mut self, no: usize, seq: u32) {
+        let instant = self.instant();
+        let lift = match self.lifts.get_mut(no) {
+            // 过期的定时器直接忽略
+            Some(lift) if lift.door.seq == seq => lift,
+            _ => return,
+        };
+        if lift.door.state == DoorState::Open && lift.is_overload() {
+            return self.overload_alarm(no);
+        }
+        match lift.door.on_timer(seq, instant) {
+            Some(millis) => self.door_timer(no, millis),
+            // 门关好了，静止的电梯看看有没有要去的楼层
+            None if lift.state == State::Stop => self.start_lift(no),
+            None => self.leave_floor(no),
+        }
+    }
+
+    // 超载报警：门一直开着，最后进来的乘客退出，直到不再超载
+    fn overload_alarm(&mut self, no: usize) {
+        let behaviour = self.behaviour;
+        let instant = self.instant();
+        let lift = &mut self.lifts[no];
+        let p = match lift.step_out() {
+            Some(p) => p,
+            None => return,
+        };
+        log!(self, "电梯{}超载，载重{:.0}千克，{}号乘客退出", no, lift.load() + p.weight, p.id);
+        let floor = p.origin;
+        let direction = p.direction();
+        let millis = lift.door.hold((behaviour.alighting * 1000.0) as u32, instant);
+        self.passengers.step_out(p);
+        if let Some(millis) = millis {
+            self.door_timer(no, millis);
+        }
+        // 退出的乘客重新呼梯
+        self.hall_call(floor, direction);
+    }
+
+    // 按电梯里的开门、关门按钮
+    pub fn press_door(&mut self, no: usize, open: bool) {
+        let instant = self.instant();
+        let lift = match self.lifts.get_mut(no) {
+            Some(lift) => lift,
+            // 电梯已经被移除了
+            None => return,
+        };
+        let millis = if !open && lift.is_overload() {
+            // 超载时按关门按钮不起作用
+            None
+        } else if !open {
+            lift.door.press_close(instant)
+        } else if lift.door.is_closed() && lift.state == State::Stop {
+            // 静止的电梯可以直接开门
+            lift.open_door(0, instant)
+        } else {
+            lift.door.press_open(instant)
+        };
+        if let Some(millis) = millis {
+            self.door_timer(no, millis);
+        }
+    }
+
+    // 门关好后，电梯离开楼层
+    fn leave_floor(&mut self, no: usize) {
+        let lift = &mut self.lifts[no];
+        match lift.state {
+            State::GoingUpSuspend => lift.state = State::GoingUp,
+            State::GoingDownSuspend => lift.state = State::GoingDown,
+            _ => {}
+        };
+        lift.can_click_btn = lift.persons() > 0;
+        log!(self, "leave_floor {}", lift.to_string());
+        self.run_lift(no)
+    }
+
+    // 电梯里的楼层按钮被点击
+    pub fn click_lift_floor(&mut self, no: usize, floor: TFloor) {
+        let instant = self.instant();
+        let lift = match self.lifts.get_mut(no) {
+            Some(lift) => lift,
+            // 电梯已经被移除了
+            None => return,
+        };
+        if lift.can_click_btn {
+            let btn = match lift.elevator_btns
+                .iter_mut()
+                .find(|o| o.floor == floor) {
+                Some(btn) => btn,
+                // 电梯不停靠这一层
+                None => return,
+            };
+            btn.is_active = !btn.is_active;
+            btn.last_pressed = Some(instant);
+            let first_floor = lift.stop_floors.iter().next().map(|o| *o.0);
+            if btn.is_active {
+                let can_insert = match first_floor {
+                    None => true,
+                    Some(_) => {
+                        match lift.state {
+                            State::GoingUp | State::GoingUpSuspend => floor > lift.cur_floor,
+                            State::GoingDown | State::GoingDownSuspend => floor < lift.cur_floor,
+                            State::Stop => true,
+                            _ => false,
+                        }
+                    }
+                };
+                if can_insert {
+                    lift.stop_floors.insert(floor, None);
+                }
+            } else {
+                if lift.stop_floors.len() > 1 {
+                    // 超过一个输入时， 才允许删除
+                    lift.stop_floors.remove(&floor);
+                } else {
+                    btn.is_active = true;
+                }
+            }
+
+        }
+        log!(self, "{}, {}", lift.to_string(), lift.stop_floors
+            .keys()
+            .into_iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>()
+            .join(","));
+        // 静止的电梯有了输入后，马上出发
+        self.start_lift(no)
+    }
+
+    // 返回电梯在这个楼层要接的人的方向
+    fn remove_wait_floor(wait_floors: &mut LinkedList<WaitFloorTxtState>, floor: TFloor, lift: &mut Lift) -> Option<Direction>
+    {
+        let direction = lift.remove_floor(floor);
+        if let Some(direct) = direction {
+            // 删除正在等待的楼层
+            loop {
+                if let Some((idx, _)) = wait_floors
+                    .iter()
+                    .enumerate()
+                    .find(|(_, wf)| wf.floor == floor && wf.direction == direct
+                    ) {
+                    let mut after = wait_floors.split_off(idx);
+                    after.pop_front(); // 删除首部元素， 再跟原来的 list 拼接起来
+                    wait_floors.append(&mut after);
+                } else {
+                    break;
+                }
+            }
+        }
+        direction
+    }
+
+    fn new_up_down_elevator(&self, floor: TFloor, typ: FloorType) -> UpDownElevatorFloor {
+        match typ {
+            FloorType::PersonUp | FloorType::PersonDown => UpDownElevatorFloor {
+                floor,
+                typ,
+                state: EState::Noop,
+            },
+            FloorType::Elevator(no) => {
+                UpDownElevatorFloor {
+                    floor,
+                    typ,
+                    state: match self.lifts[no].state {
+                        State::Stop => EState::Stop,
+                        State::Maintaining => EState::Noop,
+                        _ => EState::Running
+                    },
+                }
+            }
+        }
+    }
+
+    fn schedule2(&mut self, floor: TFloor, direction: Direction) {
+        let mut a = vec![
+            self.new_up_down_elevator(floor, match direction {
+                Direction::Up => FloorType::PersonUp,
+                Direction::Down => FloorType::PersonDown,
+            })];
+        a.extend(self.lifts
+            .iter()
+            .filter(|lift| lift.state == State::Stop ||
+                match direction {
+                    Direction::Up => ((lift.state == State::GoingUp || lift.state == State::GoingUpSuspend)
+                        && lift.cur_floor <= floor),
+                    Direction::Down => ((lift.state == State::GoingDown || lift.state == State::GoingDownSuspend)
+                        && lift.cur_floor >= floor),
+                })
+            // 太挤的电梯，候梯的乘客也不会进去
+            .filter(|lift| lift.load_factor() < self.behaviour.balk_load_factor)
+            .filter(|lift| self.can_serve(lift, floor, direction))
+            .map(|o| self.new_up_down_elevator(o.cur_floor, FloorType::Elevator(o.no)))
+        );
+        a.shrink_to_fit();
+        match direction {
+            Direction::Up => a.sort(),
+            Direction::Down => a.sort_by(|a, b| b.cmp(a)),
+        }
+        let mut top_lift = None;
+        let mut down_lift = None;
+        let mut find = false;
+        for item in a {
+            match item.typ {
+                FloorType::PersonUp | FloorType::PersonDown => find = true,
+                FloorType::Elevator(no) => {
+                    if !find {
+                        top_lift = Some(no);
+                    } else {
+                        down_lift = Some(no);
+                        break;
+                    }
+                }
+            }
+        }
+        let lift_idx = match (top_lift, down_lift) {
+            (Some(top), Some(down)) => {
+                // 按运行曲线估算的到达时间来选
+                let now = self.instant();
+                let top_eta = self.lifts[top].eta(floor, now);
+                let down_eta = self.lifts[down].eta(floor, now);
+                if top_eta >= down_eta {
+                    down
+                } else {
+                    top
+                }
+            }
+            (Some(lift), None) | (None, Some(lift)) => lift,
+            (None, None) => return,
+        };
+        self.assign(lift_idx, floor, direction)
+    }
+
+    // 最短到达时间：在所有能用的电梯里，选估算到达时间加上载荷、停靠楼层的代价最小的一部
+    fn schedule_eta(&mut self, floor: TFloor, direction: Direction) {
+        let now = self.instant();
+        let weights = config().dispatch.clone();
+        let best = self.lifts
+            .iter()
+            .filter(|lift| lift.state != State::Maintaining)
+            // 太挤的电梯，候梯的乘客也不会进去
+            .filter(|lift| lift.load_factor() < self.behaviour.balk_load_factor)
+            .filter(|lift| self.can_serve(lift, floor, direction))
+            .map(|lift| (lift.no, lift.call_eta(floor, direction, now) as f64
+                + weights.load_weight * lift.load_factor()
+                + weights.stop_weight * (lift.stop_floors.len() + lift.schedule_floors.len()) as f64))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((lift_idx, _)) = best {
+            self.assign(lift_idx, floor, direction)
+        }
+    }
+
+    // 电梯能不能接 floor 楼层 direction 方向的呼叫：要停靠这一层，设置了分区的话这一层要在分区里，
+    // 而且有人候梯时，至少要停靠其中一位的目的楼层，不然派过去也没人进
+    fn can_serve(&self, lift: &Lift, floor: TFloor, direction: Direction) -> bool {
+        if !lift.serves(floor) || !config().in_zone(lift.no, floor) {
+            return false;
+        }
+        let mut destinations = self.passengers.destinations(floor, direction).peekable();
+        destinations.peek().is_none() || destinations.any(|d| lift.serves(d))
+    }
+
+    // 按调度策略把呼叫派给一部电梯，没有能用的电梯时，呼叫留着等下次调度
+    fn schedule(&mut self, floor: TFloor, direction: Direction) {
+        match self.dispatch {
+            Dispatch::NearestCar => self.schedule2(floor, direction),
+            Dispatch::LeastEta => self.schedule_eta(floor, direction),
+        }
+    }
+
+    // 把呼叫派给 lift_idx 号电梯
+    fn assign(&mut self, lift_idx: usize, floor: TFloor, direction: Direction) {
+        self.wait_floors
+            .iter_mut()
+            .filter(|wf| wf.floor == floor && wf.direction == direction)
+            .for_each(|wf| wf.is_scheduled = true);
+        let lift = &mut self.lifts[lift_idx];
+        lift.schedule_floors.insert(floor, Some(direction));
+        // 运行中的电梯会在下一层自己处理新的调度楼层， 只有原来静止的电梯才需要启动
+        if lift.state == State::Stop {
+            if lift.cur_floor > floor {
+                lift.state = State::GoingDown;
+            } else if lift.cur_floor < floor {
+                lift.state = State::GoingUp;
+            } else {
+                // 在同一个楼层时， 就开门进出人就可以了
+                match direction {
+                    Direction::Up => lift.state = State::GoingUpSuspend,
+                    Direction::Down => lift.state = State::GoingDownSuspend,
+                }
+                return self.stop_at_floor(lift_idx, floor);
+            }
+            if lift.door.is_closed() {
+                return self.run_lift(lift_idx);
+            }
+            // 门还开着，等门关好后再出发
+        }
+    }
+
+    // 楼层有人呼梯
+    pub fn hall_call(&mut self, floor: TFloor, direction: Direction) {
+        if self.wait_floors.iter().any(|wf| wf.floor == floor && wf.direction == direction) {
+            // 已经在等待的呼叫不用重复调度
+            return;
+        }
+        self.wait_floors.push_back(WaitFloorTxtState {
+            floor,
+            direction,
+            is_scheduled: false,
+        });
+        self.metrics.call(floor, direction, self.clock);
+        // 新的呼叫马上调度
+        self.schedule(floor, direction)
+    }
+
+    // 一位乘客到达候梯厅，并呼梯
+    pub fn arrive(&mut self, origin: TFloor, destination: TFloor, at: f64, walking: f64, weight: f64) {
+        // 不换乘，没有一部电梯同时停靠出发楼层和目的楼层的话，乘客到不了
+        let config = config();
+        if !self.lifts.iter().any(|lift| lift.serves(origin) && lift.serves(destination) && config.in_zone(lift.no, origin)) {
+            log!(self, "没有电梯能从{}层直达{}层，这位乘客不坐电梯了", origin, destination);
+            return;
+        }
+        self.passengers.arrive(origin, destination, at, walking, weight);
+        let direction = if destination > origin { Direction::Up } else { Direction::Down };
+        self.hall_call(origin, direction)
+    }
+
+    // 兜底：把漏掉的呼叫重新调度一次，并启动闲置但仍有目标楼层的电梯，一直闲着的电梯回到待命楼层
+    pub fn fallback_scheduling(&mut self) {
+        for no in 0..self.lifts.len() {
+            self.start_lift(no);
+        }
+        self.schedule_waiting();
+        for no in 0..self.lifts.len() {
+            self.park(no);
+        }
+    }
+
+    // 只走几层的乘客等得太久，改走楼梯
+    pub fn take_stairs(&mut self) {
+        let left = self.passengers.take_stairs(self.clock, &self.behaviour);
+        for (floor, direction) in left {
+            log!(self, "{}层有一位乘客等不及，往{}走楼梯了", floor, direction);
+            if self.passengers.waiting_count(floor, direction) == 0 {
+                self.cancel_hall_call(floor, direction);
+            }
+        }
+    }
+
+    // 候梯的人都走了，取消这个呼叫，派去的电梯不用再去
+    fn cancel_hall_call(&mut self, floor: TFloor, direction: Direction) {
+        let before = self.wait_floors.len();
+        self.wait_floors = std::mem::take(&mut self.wait_floors)
+            .into_iter()
+            .filter(|wf| wf.floor != floor || wf.direction != direction)
+            .collect();
+        if self.wait_floors.len() == before {
+            return;
+        }
+        for lift in self.lifts.iter_mut() {
+            if lift.schedule_floors.get(&floor) == Some(&Some(direction)) {
+                lift.schedule_floors.remove(&floor);
+            }
+        }
+        self.metrics.cancel(floor, direction);
+        log!(self, "{}层的{}行呼叫没人等了，已取消", floor, direction);
+    }
+
+    // 配置重新载入后，把新的参数用到电梯和乘客上，电梯现在的状态不变
+    // 界面上关掉的走楼梯保持关闭
+    pub fn reconfigure(&mut self) {
+        let config = config();
+        let stairs = self.behaviour.stairs_patience.is_some();
+        self.behaviour = config.passengers;
+        if !stairs {
+            self.behaviour.stairs_patience = None;
+        }
+        for lift in self.lifts.iter_mut() {
+            lift.apply_config(&config);
+        }
+        if let Some((generator, _)) = self.traffic.as_mut() {
+            generator.set_behaviour(self.behaviour.walking, self.behaviour.weight);
+        }
+        log!(self, "配置已更新");
+        // 新设置的待命楼层，已经闲着的电梯马上过去
+        for no in 0..self.lifts.len() {
+            self.park(no);
+        }
+    }
+
+    // 开启自动客流，已经开启的客流会被替换掉
+    pub fn start_traffic(&mut self, profile: TrafficProfile, intensity: f64, seed: u64) {
+        log!(self, "开启自动客流:{}，强度:{:.1}%，随机种子:{}", profile, intensity, seed);
+        let generator = profile.generator(intensity, seed, &self.behaviour);
+        self.traffic = Some((generator, self.clock));
+    }
+
+    pub fn stop_traffic(&mut self) {
+        self.traffic = None;
+    }
+
+    // 自动客流下一位乘客到达的时刻
+    pub fn next_arrival_at(&self) -> Option<f64> {
+        let (generator, started) = self.traffic.as_ref()?;
+        generator.next_arrival_at().map(|at| started + at)
+    }
+
+    // 自动客流里到时间的乘客到达候梯厅，并呼梯
+    pub fn on_traffic_arrival(&mut self) {
+        let now = self.clock;
+        let (arrivals, started): (Vec<Arrival>, f64) = match &mut self.traffic {
+            Some((generator, started)) => (generator.arrivals_until(now, *started), *started),
+            None => return,
+        };
+        for arrival in arrivals {
+            self.arrive(arrival.origin, arrival.destination, started + arrival.at, arrival.walking, arrival.weight);
+        }
+    }
+
+    // 重新对时：模拟时钟为 clock 时对应 epoch 这个 Instant
+    // 时钟跳变了，电梯的利用率从这时重新统计
+    pub fn set_epoch(&mut self, epoch: Instant, clock: f64) {
+        self.epoch = epoch;
+        self.epoch_clock = clock;
+        self.clock = clock;
+        self.utilisation.reset(&self.lifts, clock);
+    }
+
+    // 处理一次输入，界面、轨迹回放和批量模拟都走这里，保证几边的处理完全一样
+    // 除了界面上的操作，电梯和开关门的定时器到期、客流到达和定时检查也算输入，录制时一起记下来
+    pub fn apply_input(&mut self, input: &TraceInput) {
+        match input {
+            TraceInput::ArriveByOneFloor(no) => self.on_lift_event(*no, Simulation::arrive_by_one_floor),
+            TraceInput::DoorTimer(no, seq) => {
+                let seq = *seq;
+                self.on_lift_event(*no, |sim, no| sim.on_door_timer(no, seq));
+            }
+            TraceInput::TrafficArrival => self.on_traffic_arrival(),
+            TraceInput::PassengerTick => self.take_stairs(),
+            TraceInput::Scheduling => self.fallback_scheduling(),
+            TraceInput::HallCall { floor, destination, walking, weight } => {
+                self.arrive(*floor, *destination, self.clock, *walking, *weight);
+            }
+            TraceInput::CarCall { lift, floor } => {
+                let floor = *floor;
+                self.on_lift_event(*lift, |sim, no| sim.click_lift_floor(no, floor));
+            }
+            TraceInput::DoorOpen(no) => self.press_door(*no, true),
+            TraceInput::DoorClose(no) => self.press_door(*no, false),
+            TraceInput::Maintain(no) => self.on_lift_event(*no, Simulation::toggle_maintaining),
+            TraceInput::AddLift { floor } => {
+                self.add_lift_at(*floor);
+            }
+            TraceInput::RemoveLift => {
+                // 至少保留一部电梯
+                if self.lifts.len() > 1 {
+                    self.remove_lift();
+                }
+            }
+            TraceInput::StartTraffic { profile, intensity, seed } => self.start_traffic(*profile, *intensity, *seed),
+            TraceInput::StopTraffic => self.stop_traffic(),
+            TraceInput::Stairs(stairs) => {
+                self.behaviour.stairs_patience = if *stairs { Some(config().passengers.stairs_patience.unwrap_or(STAIRS_PATIENCE_IN_SECONDS)) } else { None };
+            }
+            TraceInput::Reconfigure(config) => {
+                set_config((**config).clone());
+                self.reconfigure();
+            }
+        }
+    }
+
+    // 模拟时钟走到 clock，并更新电梯的实时位置，每个事件处理之前调用
+    // 调度按电梯的实时位置估算到达时间，所以位置也要按模拟时钟算，不能用界面刷新时的位置
+    pub fn advance(&mut self, clock: f64) {
+        let elapsed = (clock - self.clock).max(0.0);
+        self.lifts
+            .iter_mut()
+            .for_each(|lift| lift.accrue(elapsed));
+        self.utilisation.advance(clock);
+        self.clock = clock;
+        let now = self.instant();
+        self.lifts
+            .iter_mut()
+            .for_each(|lift| lift.update_position(now));
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::building::{building, Building};
+use crate::conf::TFloor;
+use crate::config::{config, set_config, Config};
+use crate::dispatch::Dispatch;
+use crate::door::{Door, DoorState, DoorTimings};
+use crate::floor_btn::{Direction, WaitFloorTxtState};
+use crate::lift::{Lift, Wear};
+use crate::metrics::{Metrics, Utilisation};
+use crate::motion::{MotionProfile, Trip};
+use crate::passenger::{Behaviour, Passenger, PassengerQueues};
+use crate::profile::TrafficProfile;
+use crate::simulation::{Simulation, Timer};
+use crate::state::State;
+use crate::traffic::TrafficGenerator;
+
+// 快照文件的格式版本，格式有不兼容的改动时加一
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+// 电梯门的快照，只记录当前状态已经持续的时间
+#[derive(Serialize, Deserialize)]
+pub struct DoorSnapshot {
+    pub state: DoorState,
+    pub timings: DoorTimings,
+    pub seq: u32,
+    pub dwell: u32,
+    pub reopen_times: u32,
+    pub nudging: bool,
+    // 当前状态已经持续的时间，以及应该持续的时间, 单位：豪秒
+    pub elapsed: u32,
+    pub duration: u32,
+}
+
+// 行程的快照，只记录正在运行的这一层楼已经运行的时间
+#[derive(Serialize, Deserialize)]
+pub struct TripSnapshot {
+    pub origin: f32,
+    pub target: f32,
+    pub leg_start: f32,
+    pub leg_end: f32,
+    // 这一层楼已经运行的时间, 单位：秒
+    pub leg_elapsed: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LiftSnapshot {
+    pub no: usize,
+    pub state: State,
+    pub riders: Vec<Passenger>,
+    pub capacity: usize,
+    pub rated_load: f64,
+    pub cur_floor: TFloor,
+    pub position: f32,
+    pub profile: MotionProfile,
+    // 停靠的楼层
+    pub floors: Vec<TFloor>,
+    pub trip: Option<TripSnapshot>,
+    pub can_click_btn: bool,
+    pub stop_floors: BTreeMap<TFloor, Option<Direction>>,
+    pub schedule_floors: BTreeMap<TFloor, Option<Direction>>,
+    pub door: DoorSnapshot,
+    // 使用和磨损统计
+    pub wear: Wear,
+}
+
+impl LiftSnapshot {
+    pub fn capture(lift: &Lift, now: Instant) -> Self {
+        let (elapsed, duration) = lift.door.progress(now);
+        Self {
+            no: lift.no,
+            state: lift.state.clone(),
+            riders: lift.riders.clone(),
+            capacity: lift.capacity,
+            rated_load: lift.rated_load,
+            cur_floor: lift.cur_floor,
+            position: lift.position,
+            profile: lift.profile,
+            floors: lift.floors.iter().copied().collect(),
+            trip: lift.trip.map(|trip| TripSnapshot {
+                origin: trip.origin,
+                target: trip.target,
+                leg_start: trip.leg_start,
+                leg_end: trip.leg_end,
+                leg_elapsed: now
+                    .checked_duration_since(trip.leg_started_at)
+                    .map(|d| d.as_secs_f32())
+                    .unwrap_or(0.0)
+                    .min(trip.leg_end - trip.leg_start),
+            }),
+            can_click_btn: lift.can_click_btn,
+            stop_floors: lift.stop_floors.clone(),
+            schedule_floors: lift.schedule_floors.clone(),
+            door: DoorSnapshot {
+                state: lift.door.state,
+                timings: lift.door.timings,
+                seq: lift.door.seq,
+                dwell: lift.door.dwell,
+                reopen_times: lift.door.reopen_times,
+                nudging: lift.door.nudging,
+                elapsed,
+                duration,
+            },
+            wear: lift.wear,
+        }
+    }
+
+    // 恢复成电梯，同时返回门的定时器还剩下的时间, 单位：豪秒； 门关着时没有定时器
+    pub fn restore(self, now: Instant) -> (Lift, Option<u32>) {
+        let mut lift = Lift::new(self.no);
+        lift.state = self.state;
+        lift.riders = self.riders;
+        lift.capacity = self.capacity;
+        lift.rated_load = self.rated_load;
+        lift.cur_floor = self.cur_floor;
+        lift.position = self.position;
+        lift.profile = self.profile;
+        lift.set_floors(self.floors);
+        lift.trip = self.trip.map(|trip| Trip {
+            origin: trip.origin,
+            target: trip.target,
+            leg_start: trip.leg_start,
+            leg_end: trip.leg_end,
+            leg_started_at: now
+                .checked_sub(Duration::from_secs_f32(trip.leg_elapsed.max(0.0)))
+                .unwrap_or(now),
+        });
+        lift.can_click_btn = self.can_click_btn;
+        lift.stop_floors = self.stop_floors;
+        lift.schedule_floors = self.schedule_floors;
+        lift.wear = self.wear;
+        lift.door = Door::default();
+        lift.door.state = self.door.state;
+        lift.door.timings = self.door.timings;
+        lift.door.seq = self.door.seq;
+        lift.door.dwell = self.door.dwell;
+        lift.door.reopen_times = self.door.reopen_times;
+        lift.door.nudging = self.door.nudging;
+        let remaining = lift.door.resume(self.door.elapsed, self.door.duration, now);
+        lift.set_lift_btn_click();
+        let door_timer = if lift.door.is_closed() { None } else { Some(remaining) };
+        (lift, door_timer)
+    }
+}
+
+// 自动客流的快照，包括生成器的随机数状态
+#[derive(Serialize, Deserialize)]
+pub struct TrafficSnapshot {
+    pub generator: TrafficGenerator,
+    // 生成器启动的时刻，用模拟时钟表示, 单位：秒
+    pub started: f64,
+}
+
+// 整个模拟的快照
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    // 保存时的大楼和配置，载入时换成这里的配置，不管现在用的是哪个配置
+    pub building: Building,
+    pub config: Config,
+    // 保存时的模拟时钟, 单位：秒
+    pub clock: f64,
+    // 界面上选择的呼梯楼层
+    pub floor: TFloor,
+    pub tmp_floor: TFloor,
+    pub lifts: Vec<LiftSnapshot>,
+    // 等待调度的呼叫，按呼叫的顺序排列
+    pub wait_floors: Vec<WaitFloorTxtState>,
+    pub passengers: PassengerQueues,
+    pub behaviour: Behaviour,
+    pub dispatch: Dispatch,
+    pub profile: TrafficProfile,
+    pub intensity: f64,
+    pub seed: Option<u64>,
+    pub traffic: Option<TrafficSnapshot>,
+    // 乘客的行程、呼叫记录、还没应答的呼叫，以及电梯的忙碌时间
+    pub metrics: Metrics,
+    pub utilisation: Utilisation,
+}
+
+impl Snapshot {
+    // 模拟现在的状态，界面上选的楼层和客流取默认值
+    pub fn capture(sim: &Simulation) -> Self {
+        let now = sim.instant();
+        let config = config();
+        Self {
+            version: SNAPSHOT_VERSION,
+            building: (*building()).clone(),
+            config: (*config).clone(),
+            clock: sim.clock,
+            floor: config.building.lobby,
+            tmp_floor: config.building.lobby,
+            lifts: sim.lifts
+                .iter()
+                .map(|lift| LiftSnapshot::capture(lift, now))
+                .collect(),
+            wait_floors: sim.wait_floors.iter().copied().collect(),
+            passengers: sim.passengers.clone(),
+            behaviour: sim.behaviour,
+            dispatch: sim.dispatch,
+            profile: Default::default(),
+            intensity: config.traffic.intensity,
+            seed: None,
+            traffic: sim.traffic
+                .as_ref()
+                .map(|(generator, started)| TrafficSnapshot {
+                    generator: generator.clone(),
+                    started: *started,
+                }),
+            metrics: sim.metrics.clone(),
+            utilisation: sim.utilisation.clone(),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("快照序列化失败: {}", e))?;
+        std::fs::write(path, json)
+            .map_err(|e| format!("快照保存到{}失败: {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("快照{}读取失败: {}", path, e))?;
+        // 先只看版本号，版本不对时不用管其他字段能不能解析
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let version = serde_json::from_str::<Version>(&json)
+            .map_err(|e| format!("快照{}格式错误: {}", path, e))?
+            .version;
+        if version != SNAPSHOT_VERSION {
+            return Err(format!("快照{}的版本是{}，只支持版本{}", path, version, SNAPSHOT_VERSION));
+        }
+        let snapshot: Self = serde_json::from_str(&json)
+            .map_err(|e| format!("快照{}格式错误: {}", path, e))?;
+        snapshot.check()
+            .map_err(|e| format!("快照{}不能载入: {}", path, e))?;
+        Ok(snapshot)
+    }
+
+    // 把模拟恢复到快照保存的那一刻，模拟时钟从快照的时钟接着走，now 是这个时钟对应的 Instant
+    // 配置和大楼换成保存时的，开关门和正在运行的这一层楼还没到期的定时器，交给外面重新安排
+    pub fn restore(self, sim: &mut Simulation, now: Instant) {
+        set_config(self.config);
+        sim.take_timers();
+        sim.set_epoch(now, self.clock);
+        sim.wait_floors = self.wait_floors.into_iter().collect();
+        sim.passengers = self.passengers;
+        sim.metrics = self.metrics;
+        sim.utilisation = self.utilisation;
+        sim.behaviour = self.behaviour;
+        sim.dispatch = self.dispatch;
+        sim.traffic = self.traffic.map(|t| (t.generator, t.started));
+        sim.lifts.clear();
+        for (no, lift) in self.lifts.into_iter().enumerate() {
+            let (mut lift, door_timer) = lift.restore(now);
+            lift.no = no;
+            if let Some(millis) = door_timer {
+                sim.push_timer(Timer::Door(no, millis, lift.door.seq));
+            }
+            // 正在两层楼之间运行的电梯，把这一层楼剩下的路走完
+            if let (Some(trip), State::GoingUp | State::GoingDown) = (&lift.trip, &lift.state) {
+                let elapsed = now
+                    .checked_duration_since(trip.leg_started_at)
+                    .map(|d| d.as_secs_f32())
+                    .unwrap_or(0.0);
+                let millis = ((trip.leg_end - trip.leg_start - elapsed).max(0.0) * 1000.0) as u32;
+                sim.push_timer(Timer::RunOneFloor(no, millis));
+            }
+            sim.lifts.push(lift);
+        }
+    }
+
+    // 检查快照本身是否完整：保存时的配置要合法，大楼要和配置对得上，快照里的楼层都要在大楼里
+    pub fn check(&self) -> Result<(), String> {
+        self.config.validate()
+            .map_err(|e| format!("保存时的配置有误:\n  {}", e.join("\n  ")))?;
+        let building = &self.building;
+        if self.config.to_building()? != *building {
+            return Err("保存的大楼和保存时的配置对不上".to_string());
+        }
+        let mut floors = vec![self.floor, self.tmp_floor];
+        for lift in &self.lifts {
+            floors.push(lift.cur_floor);
+            floors.extend(lift.floors.iter().copied());
+            floors.extend(lift.stop_floors.keys().copied());
+            floors.extend(lift.schedule_floors.keys().copied());
+            floors.extend(lift.riders.iter().flat_map(|p| [p.origin, p.destination]));
+        }
+        floors.extend(self.wait_floors.iter().map(|wf| wf.floor));
+        floors.extend(self.passengers.waiting_passengers().flat_map(|p| [p.origin, p.destination]));
+        match floors.into_iter().find(|f| building.get(*f).is_none()) {
+            Some(floor) => Err(format!("{}层不在大楼里", floor)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{EventKind, EventQueue};
+
+    // 开着自动客流跑 until 秒，电梯里有人、有人在候梯、门开着
+    fn running(until: f64) -> Simulation {
+        let mut sim = Simulation::new(0.0);
+        sim.verbose = false;
+        sim.add_lift_at(1);
+        sim.add_lift_at(20);
+        sim.add_lift_at(-2);
+        sim.start_traffic(TrafficProfile::Lunch, 25.0, 11);
+        let mut queue = EventQueue::new();
+        queue.push_traffic(&sim);
+        queue.push(1.0, EventKind::PassengerTick);
+        while let Some(event) = queue.pop() {
+            if event.at > until {
+                break;
+            }
+            queue.handle(&mut sim, &event);
+        }
+        sim.advance(until);
+        sim
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}.json", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn round_trip() {
+        let sim = running(120.0);
+        let snapshot = Snapshot::capture(&sim);
+        assert!(snapshot.lifts.iter().any(|lift| !lift.riders.is_empty()));
+        assert!(snapshot.passengers.waiting_passengers().next().is_some());
+        let path = temp_path("snapshot-round-trip");
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut restored = Simulation::new(0.0);
+        restored.verbose = false;
+        loaded.restore(&mut restored, Instant::now());
+        // 恢复后马上再保存，和原来的快照一样
+        assert_eq!(serde_json::to_value(Snapshot::capture(&restored)).unwrap(),
+                   serde_json::to_value(&snapshot).unwrap());
+        // 自动客流接着产生的乘客也一样
+        let next = |sim: &Simulation| {
+            let (mut generator, started) = sim.traffic.clone().unwrap();
+            generator.arrivals_until(sim.clock + 300.0, started)
+        };
+        assert_eq!(next(&restored), next(&sim));
+    }
+
+    #[test]
+    fn rejects_floors_outside_the_building() {
+        let mut snapshot = Snapshot::capture(&running(10.0));
+        snapshot.floor = 99;
+        assert_eq!(snapshot.check().unwrap_err(), "99层不在大楼里");
+        snapshot.floor = 1;
+        snapshot.building = Building::uniform(1, 10, 3.0).unwrap();
+        assert_eq!(snapshot.check().unwrap_err(), "保存的大楼和保存时的配置对不上");
+    }
+}
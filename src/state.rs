@@ -1,7 +1,9 @@
 use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use crate::floor_btn::Direction;
 
 // 电梯状态
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum State {
     // 电梯静止不动
     Stop,
@@ -23,6 +25,17 @@ impl Default for State {
     }
 }
 
+impl State {
+    // 电梯的运行方向， 静止和维护中的电梯没有方向
+    pub fn direction(&self) -> Option<Direction> {
+        match self {
+            State::GoingUp | State::GoingUpSuspend => Some(Direction::Up),
+            State::GoingDown | State::GoingDownSuspend => Some(Direction::Down),
+            State::Stop | State::Maintaining => None,
+        }
+    }
+}
+
 impl Display for State {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use State::*;
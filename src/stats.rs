@@ -0,0 +1,159 @@
+// 批量模拟用到的统计量
+
+// 自由度 1..=30 时，双侧 95% 的 t 分布临界值
+const T_975: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+    2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+    2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+pub fn mean(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return f64::NAN;
+    }
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+// 样本标准差，少于两个样本时为 0
+pub fn std_dev(samples: &[f64]) -> f64 {
+    if samples.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(samples);
+    let var = samples.iter().map(|x| (x - m) * (x - m)).sum::<f64>() / (samples.len() - 1) as f64;
+    var.sqrt()
+}
+
+// 百分位数，p 取 0~100，相邻两个样本之间线性插值
+pub fn percentile(samples: &[f64], p: f64) -> f64 {
+    if samples.is_empty() {
+        return f64::NAN;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = (p / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+// 双侧 95% 的 t 分布临界值，自由度超过 30 时按表插值
+pub fn t_critical(df: usize) -> f64 {
+    match df {
+        0 => f64::NAN,
+        1..=30 => T_975[df - 1],
+        31..=40 => 2.042 - (df - 30) as f64 * (2.042 - 2.021) / 10.0,
+        41..=60 => 2.021 - (df - 40) as f64 * (2.021 - 2.000) / 20.0,
+        61..=120 => 2.000 - (df - 60) as f64 * (2.000 - 1.980) / 60.0,
+        _ => 1.960,
+    }
+}
+
+// 均值 95% 置信区间的半宽，少于两个样本时无法估计
+pub fn ci95(samples: &[f64]) -> f64 {
+    if samples.len() < 2 {
+        return f64::NAN;
+    }
+    t_critical(samples.len() - 1) * std_dev(samples) / (samples.len() as f64).sqrt()
+}
+
+// 一组样本的汇总
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    // 均值 95% 置信区间的半宽
+    pub ci95: f64,
+    pub p5: f64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+impl Summary {
+    pub fn of(samples: &[f64]) -> Self {
+        Self {
+            n: samples.len(),
+            mean: mean(samples),
+            std_dev: std_dev(samples),
+            ci95: ci95(samples),
+            p5: percentile(samples, 5.0),
+            p50: percentile(samples, 50.0),
+            p95: percentile(samples, 95.0),
+        }
+    }
+}
+
+// 用移动平均法找到进入稳态的位置
+// 对序列做宽度为 2 * window + 1 的移动平均，以后一半的平均值作为稳态值，
+// 从某个位置开始移动平均一直落在稳态值的 ±tolerance 以内，就认为从这里开始进入了稳态
+// 序列太短，或者一直没有稳定下来时返回 None
+pub fn steady_state_start(series: &[f64], window: usize, tolerance: f64) -> Option<usize> {
+    if series.len() < 2 * (2 * window + 1) {
+        return None;
+    }
+    let steady = mean(&series[series.len() / 2..]);
+    let band = steady.abs() * tolerance;
+    let averaged = (window..series.len() - window)
+        .map(|i| mean(&series[i - window..=i + window]))
+        .collect::<Vec<_>>();
+    // 从后往前找最后一个落在范围外的点
+    match averaged.iter().rposition(|a| (a - steady).abs() > band) {
+        None => Some(0),
+        Some(i) if i + 1 < averaged.len() / 2 => Some(i + 1 + window),
+        // 后一半里还在波动，说明没有稳定下来
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn t_critical_from_table() {
+        assert!(t_critical(0).is_nan());
+        assert_close(t_critical(1), 12.706);
+        assert_close(t_critical(4), 2.776);
+        assert_close(t_critical(10), 2.228);
+        assert_close(t_critical(30), 2.042);
+    }
+
+    #[test]
+    fn t_critical_interpolated() {
+        assert_close(t_critical(35), 2.0315);
+        assert_close(t_critical(40), 2.021);
+        assert_close(t_critical(50), 2.0105);
+        assert_close(t_critical(60), 2.000);
+        assert_close(t_critical(90), 1.990);
+        assert_close(t_critical(120), 1.980);
+        assert_close(t_critical(121), 1.960);
+        assert_close(t_critical(10_000), 1.960);
+    }
+
+    #[test]
+    fn ci95_half_width() {
+        // 标准差 sqrt(2.5)，自由度 4
+        assert_close(ci95(&[1.0, 2.0, 3.0, 4.0, 5.0]), 2.776 * 2.5f64.sqrt() / 5f64.sqrt());
+        assert_close(ci95(&[10.0, 12.0, 14.0, 16.0]), 4.107944335877334);
+        // 所有样本都一样时没有波动
+        assert_close(ci95(&[7.0, 7.0, 7.0]), 0.0);
+        assert!(ci95(&[]).is_nan());
+        assert!(ci95(&[1.0]).is_nan());
+    }
+
+    #[test]
+    fn summary_of_samples() {
+        let s = Summary::of(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(s.n, 4);
+        assert_close(s.mean, 2.5);
+        assert_close(s.std_dev, (5.0f64 / 3.0).sqrt());
+        assert_close(s.p50, 2.5);
+        assert_close(s.p5, 1.15);
+        assert_close(s.p95, 3.85);
+    }
+}
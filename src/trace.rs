@@ -0,0 +1,353 @@
+use std::collections::LinkedList;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use crate::conf::TFloor;
+use crate::config::Config;
+use crate::floor_btn::{Direction, WaitFloorTxtState};
+use crate::lift::Lift;
+use crate::passenger::PassengerQueues;
+use crate::profile::TrafficProfile;
+use crate::simulation::Simulation;
+use crate::snapshot::{Snapshot, SNAPSHOT_VERSION};
+use crate::state::State;
+
+// 输入轨迹文件的格式版本，格式有不兼容的改动时加一
+pub const TRACE_VERSION: u32 = 3;
+
+// 录制的输入，随机的部分（比如乘客的目的楼层）在录制时就已经确定下来
+// 界面里定时器按真实时间到期，早晚说不准，所以定时器到期和定时检查也都记下来，回放时在同一时刻处理
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TraceInput {
+    // 电梯运行完一层楼
+    ArriveByOneFloor(usize),
+    // 电梯门的定时器到期：电梯序号，门的状态序号
+    DoorTimer(usize, u32),
+    // 自动客流的乘客到达
+    TrafficArrival,
+    // 看看有没有人要改走楼梯
+    PassengerTick,
+    // 兜底调度
+    Scheduling,
+    // 乘客在楼层呼梯
+    HallCall {
+        floor: TFloor,
+        destination: TFloor,
+        walking: f64,
+        weight: f64,
+    },
+    // 电梯里按了楼层按钮
+    CarCall {
+        lift: usize,
+        floor: TFloor,
+    },
+    // 电梯里的开门、关门按钮
+    DoorOpen(usize),
+    DoorClose(usize),
+    // 切换电梯的维护状态
+    Maintain(usize),
+    // 新增电梯，初始楼层在录制时就随机取好
+    AddLift {
+        floor: TFloor,
+    },
+    RemoveLift,
+    // 开启、关闭自动客流
+    StartTraffic {
+        profile: TrafficProfile,
+        intensity: f64,
+        seed: u64,
+    },
+    StopTraffic,
+    // 短途乘客是否改走楼梯
+    Stairs(bool),
+    // 运行时重新载入了配置文件
+    Reconfigure(Box<Config>),
+}
+
+// 输入处理完之后的状态摘要，回放时用来发现偏差
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StateDigest {
+    // 每部电梯的楼层、运行状态、人数和停靠楼层
+    pub lifts: Vec<(TFloor, State, usize, Vec<TFloor>)>,
+    // 等待调度的呼叫
+    pub wait_floors: Vec<(TFloor, Direction)>,
+    // 候梯的人数
+    pub waiting: usize,
+}
+
+impl StateDigest {
+    pub fn capture(lifts: &[Lift], wait_floors: &LinkedList<WaitFloorTxtState>, passengers: &PassengerQueues) -> Self {
+        Self {
+            lifts: lifts
+                .iter()
+                .map(|lift| (lift.cur_floor,
+                             lift.state.clone(),
+                             lift.persons(),
+                             lift.stop_floors
+                                 .keys()
+                                 .chain(lift.schedule_floors.keys())
+                                 .copied()
+                                 .collect()))
+                .collect(),
+            wait_floors: wait_floors
+                .iter()
+                .map(|wf| (wf.floor, wf.direction))
+                .collect(),
+            waiting: passengers.total_waiting(),
+        }
+    }
+
+    // 和另一个摘要不同的地方
+    pub fn diff(&self, other: &Self) -> Vec<String> {
+        let mut ret = vec![];
+        if self.lifts.len() != other.lifts.len() {
+            ret.push(format!("电梯数量: {} != {}", self.lifts.len(), other.lifts.len()));
+        }
+        for (no, (a, b)) in self.lifts.iter().zip(other.lifts.iter()).enumerate() {
+            if a != b {
+                ret.push(format!("电梯{}: {:?} != {:?}", no, a, b));
+            }
+        }
+        if self.wait_floors != other.wait_floors {
+            ret.push(format!("等待的楼层: {:?} != {:?}", self.wait_floors, other.wait_floors));
+        }
+        if self.waiting != other.waiting {
+            ret.push(format!("候梯人数: {} != {}", self.waiting, other.waiting));
+        }
+        ret
+    }
+}
+
+// 一次输入：模拟时钟的时刻、输入、输入处理完之后的状态摘要
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TraceEvent {
+    pub at: f64,
+    pub input: TraceInput,
+    pub digest: StateDigest,
+}
+
+// 轨迹文件的第一行，记录开始录制时的完整状态
+#[derive(Serialize, Deserialize)]
+struct TraceHeader {
+    version: u32,
+    snapshot: Snapshot,
+}
+
+// 录制输入轨迹，每行一个 JSON，每次输入都马上写入文件
+pub struct TraceRecorder {
+    pub path: String,
+    writer: BufWriter<File>,
+}
+
+impl TraceRecorder {
+    pub fn create(path: &str, snapshot: Snapshot) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("轨迹文件{}创建失败: {}", path, e))?;
+        let mut r = Self {
+            path: path.to_string(),
+            writer: BufWriter::new(file),
+        };
+        r.write_line(&TraceHeader {
+            version: TRACE_VERSION,
+            snapshot,
+        })?;
+        Ok(r)
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) -> Result<(), String> {
+        self.write_line(event)
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), String> {
+        serde_json::to_writer(&mut self.writer, value)
+            .map_err(|e| format!("轨迹序列化失败: {}", e))?;
+        writeln!(self.writer)
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("轨迹写入{}失败: {}", self.path, e))
+    }
+}
+
+// 回放中的轨迹
+pub struct Replay {
+    pub path: String,
+    pub events: Vec<TraceEvent>,
+    // 下一个要回放的输入
+    pub next: usize,
+    // 出现偏差的输入个数
+    pub divergences: usize,
+}
+
+impl Replay {
+    // 读取轨迹文件，返回开始录制时的状态和回放器
+    pub fn load(path: &str) -> Result<(Snapshot, Self), String> {
+        let file = File::open(path)
+            .map_err(|e| format!("轨迹文件{}读取失败: {}", path, e))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| format!("轨迹文件{}是空的", path))?
+            .map_err(|e| format!("轨迹文件{}读取失败: {}", path, e))?;
+        let header: TraceHeader = serde_json::from_str(&header)
+            .map_err(|e| format!("轨迹文件{}第1行格式错误: {}", path, e))?;
+        if header.version != TRACE_VERSION {
+            return Err(format!("轨迹文件{}的版本是{}，只支持版本{}", path, header.version, TRACE_VERSION));
+        }
+        if header.snapshot.version != SNAPSHOT_VERSION {
+            return Err(format!("轨迹文件{}里快照的版本是{}，只支持版本{}", path, header.snapshot.version, SNAPSHOT_VERSION));
+        }
+        header.snapshot.check()
+            .map_err(|e| format!("轨迹文件{}不能回放: {}", path, e))?;
+        let mut events = vec![];
+        for (i, line) in lines.enumerate() {
+            let line = line.map_err(|e| format!("轨迹文件{}读取失败: {}", path, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            events.push(serde_json::from_str(&line)
+                .map_err(|e| format!("轨迹文件{}第{}行格式错误: {}", path, i + 2, e))?);
+        }
+        Ok((header.snapshot, Self {
+            path: path.to_string(),
+            events,
+            next: 0,
+            divergences: 0,
+        }))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    // 不打开窗口回放：从开始录制时的状态出发，每个输入（包括定时器到期）都在录制的时刻处理，
+    // 走的是和界面一样的 Simulation::apply_input，处理完和录制时的状态摘要比较
+    // 模拟要的定时器不用安排，它们到期的时刻已经录在轨迹里了
+    pub fn run(&mut self, snapshot: Snapshot) {
+        let mut sim = Simulation::new(snapshot.clock);
+        sim.verbose = false;
+        snapshot.restore(&mut sim, Instant::now());
+        while let Some(event) = self.events.get(self.next).cloned() {
+            self.next += 1;
+            sim.advance(event.at);
+            sim.apply_input(&event.input);
+            sim.take_timers();
+            let diff = event.digest.diff(&StateDigest::capture(&sim.lifts, &sim.wait_floors, &sim.passengers));
+            if !diff.is_empty() {
+                self.divergences += 1;
+                println!("回放出现偏差：第{}个输入 {:?}，时刻{:.3}秒", self.next, event.input, event.at);
+                diff.iter().for_each(|d| println!("    录制 != 回放 {}", d));
+            }
+        }
+        println!("输入轨迹{}回放完毕，{}个输入中有{}个出现偏差",
+                 self.path, self.events.len(), self.divergences);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::{EventKind, EventQueue};
+    use crate::config::config;
+
+    // 像界面里一样运行一段时间：定时器总是晚一点到期，晚多少不固定，每个输入都录下来
+    fn record(path: &str) {
+        let mut sim = Simulation::new(0.0);
+        sim.verbose = false;
+        sim.add_lift_at(1);
+        sim.add_lift_at(10);
+        let mut recorder = TraceRecorder::create(path, Snapshot::capture(&sim)).unwrap();
+        let mut inputs = vec![
+            (0.5, TraceInput::HallCall { floor: 5, destination: 12, walking: 2.0, weight: 70.0 }),
+            (3.2, TraceInput::HallCall { floor: 20, destination: 1, walking: 4.0, weight: 80.0 }),
+            (9.9, TraceInput::StartTraffic { profile: TrafficProfile::Lunch, intensity: 20.0, seed: 7 }),
+            (30.0, TraceInput::DoorOpen(0)),
+            (70.0, TraceInput::StopTraffic),
+        ].into_iter();
+        let mut next_input = inputs.next();
+        let mut queue = EventQueue::new();
+        queue.push(1.0, EventKind::PassengerTick);
+        queue.push(config().scheduling_fallback as f64, EventKind::Scheduling);
+        let mut k = 0;
+        while let Some(event) = queue.pop() {
+            if event.at > 120.0 {
+                break;
+            }
+            // 到时间的界面操作先处理
+            while let Some((at, input)) = next_input.clone().filter(|(at, _)| *at <= event.at) {
+                next_input = inputs.next();
+                sim.advance(at.max(sim.clock));
+                sim.apply_input(&input);
+                recorder.record(&TraceEvent { at: sim.clock, input: input.clone(), digest: capture(&sim) }).unwrap();
+                queue.push_timers(&mut sim);
+                if let TraceInput::StartTraffic { .. } = input {
+                    queue.push_traffic(&sim);
+                }
+            }
+            let input = match event.kind {
+                EventKind::ArriveByOneFloor(no) => TraceInput::ArriveByOneFloor(no),
+                EventKind::DoorTimer(no, seq) => TraceInput::DoorTimer(no, seq),
+                EventKind::TrafficArrival if sim.next_arrival_at() == Some(event.at) => TraceInput::TrafficArrival,
+                EventKind::TrafficArrival => continue,
+                EventKind::PassengerTick => {
+                    queue.push(event.at + 1.0, EventKind::PassengerTick);
+                    TraceInput::PassengerTick
+                }
+                EventKind::Scheduling => {
+                    queue.push(event.at + config().scheduling_fallback as f64, EventKind::Scheduling);
+                    TraceInput::Scheduling
+                }
+            };
+            k += 1;
+            let late = (k % 7) as f64 * 0.013;
+            sim.advance((event.at + late).max(sim.clock));
+            sim.apply_input(&input);
+            recorder.record(&TraceEvent { at: sim.clock, input: input.clone(), digest: capture(&sim) }).unwrap();
+            queue.push_timers(&mut sim);
+            if input == TraceInput::TrafficArrival {
+                queue.push_traffic(&sim);
+            }
+        }
+    }
+
+    fn capture(sim: &Simulation) -> StateDigest {
+        StateDigest::capture(&sim.lifts, &sim.wait_floors, &sim.passengers)
+    }
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}.jsonl", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_session() {
+        let path = temp_path("trace-replay");
+        record(&path);
+        let (snapshot, mut replay) = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // 定时器到期、客流到达都录下来了
+        assert!(replay.events.iter().any(|e| matches!(e.input, TraceInput::ArriveByOneFloor(_))));
+        assert!(replay.events.iter().any(|e| matches!(e.input, TraceInput::DoorTimer(..))));
+        assert!(replay.events.iter().any(|e| e.input == TraceInput::TrafficArrival));
+        replay.run(snapshot);
+        assert!(replay.is_finished());
+        assert_eq!(replay.divergences, 0);
+    }
+
+    #[test]
+    fn replay_reports_divergences() {
+        let path = temp_path("trace-divergence");
+        record(&path);
+        let (snapshot, mut replay) = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // 丢掉一次电梯到达，后面的状态就对不上了
+        let lost = replay.events
+            .iter()
+            .position(|e| matches!(e.input, TraceInput::ArriveByOneFloor(_)))
+            .unwrap();
+        replay.events.remove(lost);
+        replay.run(snapshot);
+        assert!(replay.divergences > 0);
+    }
+}
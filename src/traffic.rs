@@ -0,0 +1,337 @@
+use std::convert::TryFrom;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use crate::conf::TFloor;
+use crate::floor_btn::Direction;
+use crate::passenger::WeightDistribution;
+
+// 到达率随时间变化的泊松过程，到达率是分段常数
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArrivalProcess {
+    // (开始时刻, 每分钟到达的人数)，按开始时刻升序排列，时刻的单位：秒
+    // 最后一段一直持续到结束或者下一个周期
+    pub rates: Vec<(f64, f64)>,
+    // 到达率变化的周期, 单位：秒； 为 None 时不循环
+    pub period: Option<f64>,
+}
+
+impl ArrivalProcess {
+    // 到达率不变的泊松过程
+    pub fn constant(rate_per_minute: f64) -> Self {
+        Self {
+            rates: vec![(0.0, rate_per_minute)],
+            period: None,
+        }
+    }
+
+    // t 时刻的到达率, 单位：人/秒
+    pub fn rate_at(&self, t: f64) -> f64 {
+        let t = match self.period {
+            Some(period) if period > 0.0 => t % period,
+            _ => t,
+        };
+        self.rates
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= t)
+            .or_else(|| self.rates.first())
+            .map(|(_, rate)| rate.max(0.0) / 60.0)
+            .unwrap_or(0.0)
+    }
+
+    fn max_rate(&self) -> f64 {
+        self.rates
+            .iter()
+            .map(|(_, rate)| rate.max(0.0) / 60.0)
+            .fold(0.0, f64::max)
+    }
+
+    // 不再有人到达了
+    fn is_exhausted(&self, t: f64) -> bool {
+        match (self.period, self.rates.last()) {
+            (None, Some((start, rate))) => t >= *start && *rate <= 0.0,
+            _ => false,
+        }
+    }
+
+    // 用稀疏法（thinning）产生 t 之后的下一个到达时刻，不再有人到达时返回 None
+    pub fn next_arrival<R: Rng>(&self, t: f64, rng: &mut R) -> Option<f64> {
+        let max_rate = self.max_rate();
+        if max_rate <= 0.0 {
+            return None;
+        }
+        let mut t = t;
+        loop {
+            // gen 的取值范围是 [0, 1)，取 1 - u 避免对 0 取对数
+            let u: f64 = rng.gen();
+            t += -(1.0 - u).ln() / max_rate;
+            if rng.gen::<f64>() * max_rate < self.rate_at(t) {
+                return Some(t);
+            }
+            if self.is_exhausted(t) {
+                return None;
+            }
+        }
+    }
+}
+
+// 起止楼层矩阵（OD 矩阵），weights[i][j] 是从 floors[i] 去 floors[j] 的相对权重
+// 保存时只保存楼层和权重，读取时重新校验
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "OdWeights", into = "OdWeights")]
+pub struct OdMatrix {
+    pub floors: Vec<TFloor>,
+    pub weights: Vec<Vec<f64>>,
+    dist: WeightedIndex<f64>,
+}
+
+impl OdMatrix {
+    pub fn new(floors: Vec<TFloor>, weights: Vec<Vec<f64>>) -> Result<Self, String> {
+        if weights.len() != floors.len() || weights.iter().any(|row| row.len() != floors.len()) {
+            return Err(format!("OD 矩阵必须是 {0}x{0} 的方阵", floors.len()));
+        }
+        // 出发楼层和目的楼层相同的权重不算
+        let flat = weights
+            .iter()
+            .enumerate()
+            .flat_map(|(i, row)| row
+                .iter()
+                .enumerate()
+                .map(move |(j, w)| if i == j { 0.0 } else { *w }))
+            .collect::<Vec<_>>();
+        if let Some(w) = flat.iter().find(|w| !w.is_finite() || **w < 0.0) {
+            return Err(format!("OD 矩阵的权重不能是 {}", w));
+        }
+        let dist = WeightedIndex::new(&flat)
+            .map_err(|e| format!("OD 矩阵的权重不能全为 0: {}", e))?;
+        Ok(Self {
+            floors,
+            weights,
+            dist,
+        })
+    }
+
+    // 任意两层之间的出行都一样多
+    pub fn uniform(floors: Vec<TFloor>) -> Self {
+        let n = floors.len();
+        Self::new(floors, vec![vec![1.0; n]; n]).unwrap()
+    }
+
+    // 随机抽取一对出发楼层和目的楼层
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> (TFloor, TFloor) {
+        let idx = self.dist.sample(rng);
+        let n = self.floors.len();
+        (self.floors[idx / n], self.floors[idx % n])
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct OdWeights {
+    floors: Vec<TFloor>,
+    weights: Vec<Vec<f64>>,
+}
+
+impl TryFrom<OdWeights> for OdMatrix {
+    type Error = String;
+
+    fn try_from(o: OdWeights) -> Result<Self, Self::Error> {
+        Self::new(o.floors, o.weights)
+    }
+}
+
+impl From<OdMatrix> for OdWeights {
+    fn from(o: OdMatrix) -> Self {
+        Self {
+            floors: o.floors,
+            weights: o.weights,
+        }
+    }
+}
+
+// 生成器产生的一位乘客
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Arrival {
+    // 到达时刻，相对于生成器启动的时刻, 单位：秒
+    pub at: f64,
+    pub origin: TFloor,
+    pub destination: TFloor,
+    // 从候梯的地方走到轿厢门口的时间, 单位：秒
+    pub walking: f64,
+    // 体重, 单位：千克
+    pub weight: f64,
+}
+
+impl Arrival {
+    pub fn direction(&self) -> Direction {
+        if self.destination > self.origin {
+            Direction::Up
+        } else {
+            Direction::Down
+        }
+    }
+}
+
+// 客流生成器：按到达过程产生乘客，按 OD 矩阵给乘客分配起止楼层
+// 随机数发生器的状态也会保存下来，恢复后产生的乘客和保存前完全一样
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrafficGenerator {
+    pub process: ArrivalProcess,
+    pub od: OdMatrix,
+    pub seed: u64,
+    // 乘客走到轿厢门口的时间范围, 单位：秒
+    pub walking: (f64, f64),
+    // 乘客的体重分布
+    pub weight: WeightDistribution,
+    rng: ChaCha12Rng,
+    next: Option<Arrival>,
+}
+
+impl TrafficGenerator {
+    pub fn new(process: ArrivalProcess, od: OdMatrix, seed: u64,
+               walking: (f64, f64), weight: WeightDistribution) -> Self {
+        let mut r = Self {
+            process,
+            od,
+            seed,
+            walking,
+            weight,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            next: None,
+        };
+        r.next = r.generate(0.0);
+        r
+    }
+
+    // 配置改了乘客的走路时间和体重分布，还没到达的下一位乘客也要按新的参数重新取
+    pub fn set_behaviour(&mut self, walking: (f64, f64), weight: WeightDistribution) {
+        self.walking = walking;
+        self.weight = weight;
+        if let Some(next) = self.next.as_mut() {
+            next.walking = sample_walking(walking, &mut self.rng);
+            next.weight = weight.sample(&mut self.rng);
+        }
+    }
+
+    // 下一位乘客的到达时刻
+    pub fn next_arrival_at(&self) -> Option<f64> {
+        self.next.map(|a| a.at)
+    }
+
+    // 取出 now 时刻之前到达的所有乘客，生成器在 started 时刻启动
+    // 按 started + at 比较，和外面安排到达事件时算的时刻完全一样，不会因为换算的误差漏掉到期的乘客
+    pub fn arrivals_until(&mut self, now: f64, started: f64) -> Vec<Arrival> {
+        let mut ret = vec![];
+        while let Some(arrival) = self.next {
+            if started + arrival.at > now {
+                break;
+            }
+            ret.push(arrival);
+            self.next = self.generate(arrival.at);
+        }
+        ret
+    }
+
+    fn generate(&mut self, after: f64) -> Option<Arrival> {
+        let at = self.process.next_arrival(after, &mut self.rng)?;
+        let (origin, destination) = self.od.sample(&mut self.rng);
+        let walking = sample_walking(self.walking, &mut self.rng);
+        let weight = self.weight.sample(&mut self.rng);
+        Some(Arrival {
+            at,
+            origin,
+            destination,
+            walking,
+            weight,
+        })
+    }
+}
+
+// 在 walking 范围内随机取一个走到轿厢门口的时间
+pub fn sample_walking<R: Rng>(walking: (f64, f64), rng: &mut R) -> f64 {
+    let (lo, hi) = walking;
+    if hi > lo {
+        rng.gen_range(lo..hi)
+    } else {
+        lo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(process: ArrivalProcess, od: OdMatrix, seed: u64) -> TrafficGenerator {
+        TrafficGenerator::new(process, od, seed, (1.0, 5.0), WeightDistribution::default())
+    }
+
+    #[test]
+    fn rates_are_piecewise_and_periodic() {
+        let process = ArrivalProcess {
+            rates: vec![(0.0, 60.0), (10.0, 120.0), (20.0, 0.0)],
+            period: Some(30.0),
+        };
+        assert_eq!(process.rate_at(5.0), 1.0);
+        assert_eq!(process.rate_at(15.0), 2.0);
+        assert_eq!(process.rate_at(25.0), 0.0);
+        assert_eq!(process.rate_at(45.0), 2.0);
+    }
+
+    #[test]
+    fn same_seed_same_arrivals() {
+        let od = OdMatrix::uniform(vec![1, 2, 3, 4]);
+        let mut a = generator(ArrivalProcess::constant(30.0), od.clone(), 42);
+        let mut b = generator(ArrivalProcess::constant(30.0), od.clone(), 42);
+        let mut c = generator(ArrivalProcess::constant(30.0), od, 43);
+        let arrivals = a.arrivals_until(600.0, 0.0);
+        assert!(arrivals.len() > 200, "{}", arrivals.len());
+        assert_eq!(arrivals, b.arrivals_until(600.0, 0.0));
+        assert_ne!(arrivals, c.arrivals_until(600.0, 0.0));
+        // 分几次取和一次取完是一样的
+        let mut d = generator(ArrivalProcess::constant(30.0), OdMatrix::uniform(vec![1, 2, 3, 4]), 42);
+        let mut split = d.arrivals_until(100.0, 0.0);
+        split.extend(d.arrivals_until(600.0, 0.0));
+        assert_eq!(arrivals, split);
+    }
+
+    #[test]
+    fn arrivals_follow_the_process_and_od() {
+        // 前 60 秒没有人到达，之后每分钟 60 人，只从 1 层去 3 层
+        let process = ArrivalProcess {
+            rates: vec![(0.0, 0.0), (60.0, 60.0)],
+            period: None,
+        };
+        let od = OdMatrix::new(vec![1, 2, 3], vec![
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+        ]).unwrap();
+        let mut g = generator(process, od, 7);
+        let arrivals = g.arrivals_until(660.0, 0.0);
+        assert!(arrivals.iter().all(|a| a.at >= 60.0 && a.origin == 1 && a.destination == 3));
+        assert!(arrivals.iter().all(|a| a.walking >= 1.0 && a.walking < 5.0));
+        // 600 秒里平均到达 600 人
+        assert!((500..700).contains(&arrivals.len()), "{}", arrivals.len());
+    }
+
+    #[test]
+    fn stops_when_the_last_rate_is_zero() {
+        let process = ArrivalProcess {
+            rates: vec![(0.0, 60.0), (30.0, 0.0)],
+            period: None,
+        };
+        let mut g = generator(process, OdMatrix::uniform(vec![1, 2]), 1);
+        let arrivals = g.arrivals_until(f64::MAX, 0.0);
+        assert!(arrivals.iter().all(|a| a.at < 30.0));
+        assert_eq!(g.next_arrival_at(), None);
+    }
+
+    #[test]
+    fn od_matrix_rejects_bad_weights() {
+        assert_eq!(OdMatrix::new(vec![1, 2], vec![vec![1.0; 3]; 2]).unwrap_err(), "OD 矩阵必须是 2x2 的方阵");
+        assert_eq!(OdMatrix::new(vec![1, 2], vec![vec![0.0, -1.0], vec![0.0, 0.0]]).unwrap_err(), "OD 矩阵的权重不能是 -1");
+        // 对角线上的权重不算
+        assert!(OdMatrix::new(vec![1, 2], vec![vec![1.0, 0.0], vec![0.0, 1.0]]).is_err());
+    }
+}
@@ -1,23 +1,33 @@
 use rand::{Rng, thread_rng};
-use crate::conf::{MAX_FLOOR, MIN_FLOOR, TFloor};
+use crate::building::building;
+use crate::conf::TFloor;
+use crate::floor_btn::Direction;
 
 pub fn random_num(start: i32, end: i32) -> i32 {
     thread_rng().gen_range(start..=end)
 }
 
-pub fn random_floor() -> i32 {
-    // 楼层不能是 0
-    let mut ret = 0;
-    loop {
-        ret = random_num(MIN_FLOOR, MAX_FLOOR);
-        if ret != 0 { break; }
-    }
-    ret
+// 随机选一个楼层
+pub fn random_floor() -> TFloor {
+    building().random_floor(&mut thread_rng())
 }
 
-pub fn random_person_num() -> i32 {
-    random_num(0, 20)
+// 从 origin 楼层出发，往 direction 方向随机选一个目的楼层
+pub fn random_destination(origin: TFloor, direction: Direction) -> Option<TFloor> {
+    building().random_destination(origin, direction, &mut thread_rng())
+}
+
+// 所有楼层，从低到高
+pub fn all_floors() -> Vec<TFloor> {
+    building().numbers()
+}
+
+// 某方向上的下一层楼，已经到顶或者到底时返回 None
+pub fn next_floor(floor: TFloor, direction: Direction) -> Option<TFloor> {
+    building().next(floor, direction)
+}
+
+// 楼层所在的高度, 单位：米； 最低楼层的高度为 0
+pub fn floor_position(floor: TFloor) -> f32 {
+    building().position(floor)
 }
-pub fn random_bool() -> bool {
-    random_num(0, 20) > 10
-}
\ No newline at end of file